@@ -1,69 +1,981 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::{OsStr, OsString};
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, Read, Write};
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::settings;
+use crate::shell::{Shell, ShellKind};
 
 pub type Env = BTreeMap<OsString, OsString>;
 
 pub struct EnvrcContext {
-    pub envrc: std::fs::File,
+    /// The file defining the environment. Despite the name, this is not necessarily an .envrc, see
+    /// `source`.
+    pub envrc_path: PathBuf,
+    pub source: EnvSource,
     pub root: PathBuf,
     pub env_cache_path: PathBuf,
     pub env_cache_dir: PathBuf,
+    /// With the `layered` setting, the .envrc files in parent directories that are evaluated before
+    /// this one, outermost first.
+    pub parents: Vec<EnvrcLayer>,
+}
+
+/// A single .envrc and its cache. Without layering, the context consists of just one layer.
+#[derive(Clone, Debug)]
+pub struct EnvrcLayer {
+    pub envrc_path: PathBuf,
+    pub source: EnvSource,
+    pub root: PathBuf,
+    pub env_cache_path: PathBuf,
+}
+
+impl EnvrcContext {
+    /// All .envrc files to evaluate, outermost first and ending with the nearest one.
+    pub fn layers(&self) -> Vec<EnvrcLayer> {
+        let mut layers = self.parents.clone();
+        layers.push(EnvrcLayer {
+            envrc_path: self.envrc_path.clone(),
+            source: self.source,
+            root: self.root.clone(),
+            env_cache_path: self.env_cache_path.clone(),
+        });
+        layers
+    }
+}
+
+/// The kinds of files fastenv loads an environment from. If a directory contains several of them,
+/// the first one in this order wins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvSource {
+    /// A declarative TOML file, evaluated by fastenv itself without running a shell.
+    Toml,
+    /// A bash script, as used by direnv.
+    Envrc,
+    /// KEY=value pairs, parsed by fastenv itself without running a shell.
+    Dotenv,
+    /// Tool versions as used by asdf and mise, whose install directories are added to PATH.
+    ToolVersions,
+}
+
+impl EnvSource {
+    pub const ALL: [EnvSource; 4] = [
+        EnvSource::Toml,
+        EnvSource::Envrc,
+        EnvSource::Dotenv,
+        EnvSource::ToolVersions,
+    ];
+
+    /// The sources looked for unless the `sources` setting says otherwise. The others are opt-in,
+    /// so that a .env or .tool-versions file in a subdirectory does not hide the .envrc above it.
+    pub const DEFAULT: [EnvSource; 1] = [EnvSource::Envrc];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            EnvSource::Toml => ".fastenv.toml",
+            EnvSource::Envrc => ".envrc",
+            EnvSource::Dotenv => ".env",
+            EnvSource::ToolVersions => ".tool-versions",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EnvSource::Toml => "toml",
+            EnvSource::Envrc => "envrc",
+            EnvSource::Dotenv => "dotenv",
+            EnvSource::ToolVersions => "tool-versions",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        EnvSource::ALL
+            .into_iter()
+            .find(|source| source.as_str() == value)
+    }
+}
+
+/// Parse a comma-separated list of sources, such as `envrc,toml`, into the kinds of files to look
+/// for in order of precedence. An empty list means the default.
+pub fn parse_sources(value: &str) -> Option<Vec<EnvSource>> {
+    if value.is_empty() {
+        return Some(EnvSource::DEFAULT.to_vec());
+    }
+
+    let mut sources = Vec::new();
+    for name in value.split(',') {
+        sources.push(EnvSource::from_str(name.trim())?);
+    }
+    // precedence does not depend on the order given
+    sources.sort_by_key(|source| EnvSource::ALL.iter().position(|x| x == source));
+    sources.dedup();
+    Some(sources)
+}
+
+/// Find the file defining the environment of `dir` itself, without looking at parents.
+pub fn find_source(dir: &Path, sources: &[EnvSource]) -> Option<(PathBuf, EnvSource)> {
+    sources.iter().find_map(|&source| {
+        let path = dir.join(source.file_name());
+        // a .env directory, such as a virtualenv, does not count
+        Some((path, source)).filter(|(path, _)| path.is_file())
+    })
+}
+
+/// List the file names of `sources` for messages, such as `.envrc or .env`.
+pub fn describe_sources(sources: &[EnvSource]) -> String {
+    let names: Vec<_> = sources.iter().map(|source| source.file_name()).collect();
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => "any file".to_owned(),
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("failed to find .envrc in current or any parent directory")]
-    NoEnvrc,
+    /// None of the configured sources, listed as by `describe_sources`, was found.
+    #[error("failed to find {0} in current or any parent directory")]
+    NoEnvrc(String),
     #[error(transparent)]
     Io(#[from] io::Error),
-    #[error("failed to find QUICKENV_HOME or HOME")]
-    NoQuickenvHome,
+    #[error("failed to find FASTENV_HOME or HOME")]
+    NoFastenvHome,
     #[error("failed to get current directory")]
     CurrentDir(#[source] io::Error),
+    #[error("invalid FASTENV_LAYERED_BOUNDARY {0}")]
+    InvalidLayeringBoundary(PathBuf, #[source] io::Error),
+    #[error("failed to lock {0}")]
+    Lock(PathBuf, #[source] io::Error),
+    #[error("env cache at {0} was written by a newer version of fastenv")]
+    UnsupportedCacheVersion(PathBuf),
+    #[error("env cache at {0} is corrupt")]
+    CorruptCache(PathBuf),
 }
 
 pub fn resolve_envrc_context(fastenv_home: &Path) -> Result<EnvrcContext, Error> {
     let mut root = std::env::current_dir().map_err(Error::CurrentDir)?;
 
-    let (envrc_path, envrc) = loop {
-        let path = root.join(".envrc");
-        if let Ok(f) = std::fs::File::open(&path) {
+    let sources = &settings::get().sources;
+
+    let (envrc_path, source) = loop {
+        if let Some((path, source)) = find_source(&root, sources) {
             log::debug!("loading {}", path.display());
-            break (path, f);
+            break (path, source);
         }
 
         if !root.pop() {
-            return Err(Error::NoEnvrc);
+            return Err(Error::NoEnvrc(describe_sources(sources)));
         }
     };
 
     let env_cache_dir = fastenv_home.join("envs/");
 
-    let mut env_hasher = blake3::Hasher::new();
-    env_hasher.update(envrc_path.as_os_str().as_bytes());
-    let env_cache_path = env_cache_dir.join(hex::encode(env_hasher.finalize().as_bytes()));
+    // Each layer's cache is keyed on the whole chain of .envrc files leading up to it, since its
+    // contents depend on all of them. A single layer is keyed on just its own path.
+    let mut chain = Vec::new();
+    let mut parents = Vec::new();
+    if let Some(boundary) = get_layering_boundary()? {
+        let mut dir = root.clone();
+        while dir.pop() && dir.starts_with(&boundary) {
+            if let Some((path, source)) = find_source(&dir, sources) {
+                log::debug!("layering on top of {}", path.display());
+                parents.push(EnvrcLayer {
+                    envrc_path: path,
+                    source,
+                    root: dir.clone(),
+                    env_cache_path: PathBuf::new(),
+                });
+            }
+        }
+        parents.reverse();
+
+        for parent in &mut parents {
+            chain.push(parent.envrc_path.clone());
+            parent.env_cache_path = env_cache_dir.join(hash_paths(&chain));
+        }
+    }
+    chain.push(envrc_path.clone());
+    let env_cache_path = env_cache_dir.join(hash_paths(&chain));
 
     Ok(EnvrcContext {
         root,
         env_cache_dir,
-        envrc,
+        envrc_path,
+        source,
         env_cache_path,
+        parents,
     })
 }
 
+/// Return the outermost directory whose .envrc is layered beneath nested ones, or `None` if
+/// layering is disabled. The `layered` setting enables layering, `layered_boundary` limits it to a
+/// directory such as the root of a monorepo.
+fn get_layering_boundary() -> Result<Option<PathBuf>, Error> {
+    let settings = settings::get();
+    if !settings.layered {
+        return Ok(None);
+    }
+
+    match settings.layered_boundary {
+        Some(ref boundary) => {
+            let boundary = std::fs::canonicalize(boundary)
+                .map_err(|e| Error::InvalidLayeringBoundary(boundary.clone(), e))?;
+            Ok(Some(boundary))
+        }
+        None => Ok(Some(PathBuf::from("/"))),
+    }
+}
+
+/// Hex-encoded hash of a path, used to name per-.envrc files in the fastenv home.
+pub fn hash_path(path: &Path) -> String {
+    hash_paths(&[path])
+}
+
+fn hash_paths(paths: &[impl AsRef<Path>]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            hasher.update(b"\0");
+        }
+        hasher.update(path.as_ref().as_os_str().as_bytes());
+    }
+    hex::encode(hasher.finalize().as_bytes())
+}
+
 pub fn get_fastenv_home() -> Result<PathBuf, Error> {
-    if let Ok(home) = std::env::var("QUICKENV_HOME") {
-        Ok(Path::new(&home).to_owned())
+    if let Some((home, _)) = settings::env_var_os("FASTENV_HOME") {
+        Ok(PathBuf::from(home))
     } else if let Ok(home) = std::env::var("HOME") {
         Ok(Path::new(&home).join(".fastenv/"))
     } else {
-        Err(Error::NoQuickenvHome)
+        Err(Error::NoFastenvHome)
+    }
+}
+
+const DIRENV_PRELUDE: &str = r#"eval "$(direnv stdlib)""#;
+
+/// Loads fastenv's built-in stdlib. `__fastenv_bin` is set by fastenv before the prelude runs.
+const BUILTIN_PRELUDE: &str = r#"eval "$("$__fastenv_bin" stdlib)""#;
+
+/// Loads the reduced built-in stdlib for shells other than bash.
+const POSIX_PRELUDE: &str = r#"eval "$("$__fastenv_bin" stdlib --posix)""#;
+
+/// Return the code to run before an .envrc evaluated with `shell`. The `prelude` setting takes
+/// precedence, otherwise `stdlib` picks between direnv's stdlib and fastenv's built-in one. By
+/// default direnv's stdlib is used if direnv is installed. Shells other than bash always get the
+/// reduced built-in stdlib.
+pub fn get_prelude(shell: &Shell) -> String {
+    let settings = settings::get();
+    if let Some(ref prelude) = settings.prelude {
+        return prelude.clone();
+    }
+
+    if shell.kind != ShellKind::Bash {
+        return POSIX_PRELUDE.to_owned();
+    }
+
+    let prelude = match settings.stdlib {
+        Stdlib::Auto => {
+            if which::which("direnv").is_ok() {
+                DIRENV_PRELUDE
+            } else {
+                BUILTIN_PRELUDE
+            }
+        }
+        Stdlib::Direnv => DIRENV_PRELUDE,
+        Stdlib::Builtin => BUILTIN_PRELUDE,
+    };
+
+    prelude.to_owned()
+}
+
+/// Check whether `prelude` is one `get_prelude` may return for `shell` with the current settings.
+/// Unlike `get_prelude`, this does not look for direnv, as shims check it on every run and may
+/// well have a different PATH than 'fastenv reload' had.
+pub fn prelude_matches(shell: &Shell, prelude: &str) -> bool {
+    let settings = settings::get();
+    if let Some(ref expected) = settings.prelude {
+        return prelude == expected;
+    }
+
+    if shell.kind != ShellKind::Bash {
+        return prelude == POSIX_PRELUDE;
+    }
+
+    match settings.stdlib {
+        Stdlib::Auto => prelude == DIRENV_PRELUDE || prelude == BUILTIN_PRELUDE,
+        Stdlib::Direnv => prelude == DIRENV_PRELUDE,
+        Stdlib::Builtin => prelude == BUILTIN_PRELUDE,
+    }
+}
+
+/// Which stdlib .envrc files are evaluated with, as chosen by the `stdlib` setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stdlib {
+    /// direnv's stdlib if direnv is installed, otherwise the built-in one.
+    Auto,
+    Direnv,
+    Builtin,
+}
+
+impl Stdlib {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "" | "auto" => Some(Stdlib::Auto),
+            "direnv" => Some(Stdlib::Direnv),
+            "builtin" => Some(Stdlib::Builtin),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when a shim finds that the env cache no longer matches its .envrc.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StalePolicy {
+    Warn,
+    Error,
+    Ignore,
+}
+
+impl StalePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "" | "warn" => Some(StalePolicy::Warn),
+            "error" => Some(StalePolicy::Error),
+            "ignore" => Some(StalePolicy::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Take an exclusive lock on reloading each layer of the given context, blocking until any other
+/// fastenv process holding one of them is done. Reloading also writes the caches of parent layers,
+/// which may be shared with contexts in sibling directories. Layers are locked outermost first, so
+/// that such reloads cannot deadlock. The locks are released when the returned files are dropped.
+pub fn lock_env_cache(ctx: &EnvrcContext) -> Result<Vec<std::fs::File>, Error> {
+    std::fs::create_dir_all(&ctx.env_cache_dir)?;
+    ctx.layers()
+        .iter()
+        .map(|layer| {
+            let lock_path = get_lock_path(&layer.env_cache_path);
+            let file =
+                std::fs::File::create(&lock_path).map_err(|e| Error::Lock(lock_path.clone(), e))?;
+            file.lock().map_err(|e| Error::Lock(lock_path, e))?;
+            Ok(file)
+        })
+        .collect()
+}
+
+/// The lock file guarding reloads of the env cache at `env_cache_path`.
+pub fn get_lock_path(env_cache_path: &Path) -> PathBuf {
+    let mut lock_path = env_cache_path.to_owned().into_os_string();
+    lock_path.push(".lock");
+    PathBuf::from(lock_path)
+}
+
+/// The file the output of .envrc is written to with the `envrc_log` setting, for the env cache at
+/// `env_cache_path`.
+pub fn get_log_path(env_cache_path: &Path) -> PathBuf {
+    let mut log_path = env_cache_path.to_owned().into_os_string();
+    log_path.push(".log");
+    PathBuf::from(log_path)
+}
+
+/// How often using an env cache is recorded, see `mark_env_cache_used`.
+const MARK_USED_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Record that the env cache was used by bumping the modification time of each layer's cache,
+/// which is what 'fastenv gc --older-than' goes by. To keep shims fast, this only writes to the
+/// file system about once an hour.
+pub fn mark_env_cache_used(ctx: &EnvrcContext) -> Result<(), Error> {
+    let now = SystemTime::now();
+    for layer in ctx.layers() {
+        let modified = std::fs::metadata(&layer.env_cache_path)?.modified()?;
+        if now
+            .duration_since(modified)
+            .is_ok_and(|age| age < MARK_USED_INTERVAL)
+        {
+            continue;
+        }
+
+        std::fs::File::options()
+            .append(true)
+            .open(&layer.env_cache_path)?
+            .set_modified(now)?;
+    }
+
+    Ok(())
+}
+
+/// Fingerprint of a file as it was when the env cache was computed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    /// Modification time in nanoseconds since the unix epoch.
+    pub mtime: u128,
+    pub size: u64,
+    /// Hex-encoded blake3 hash of the file contents.
+    pub hash: String,
+}
+
+impl FileStamp {
+    pub fn new(metadata: &std::fs::Metadata, contents: &[u8]) -> Self {
+        FileStamp {
+            mtime: get_mtime(metadata),
+            size: metadata.len(),
+            hash: blake3::hash(contents).to_hex().to_string(),
+        }
+    }
+
+    /// Stamp the file at `path`, or return `None` if it does not exist.
+    pub fn from_path(path: &Path) -> Result<Option<Self>, Error> {
+        let mut file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return Ok(Some(FileStamp::new(&metadata, b"")));
+        }
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(Some(FileStamp::new(&metadata, &contents)))
+    }
+
+    /// Check whether the file at `path` still matches this stamp.
+    ///
+    /// The content hash is only computed if the cheaper checks are inconclusive, so that merely
+    /// touching a file does not invalidate the cache.
+    pub fn matches(&self, path: &Path) -> Result<bool, Error> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if metadata.len() != self.size {
+            return Ok(false);
+        }
+
+        if get_mtime(&metadata) == self.mtime {
+            return Ok(true);
+        }
+
+        Ok(FileStamp::from_path(path)?.is_some_and(|stamp| stamp.hash == self.hash))
+    }
+}
+
+/// A file the .envrc declared as a dependency using `watch_file` (or indirectly through
+/// `source_env`, `dotenv` and friends).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchedFile {
+    pub path: PathBuf,
+    /// `None` if the file did not exist when the cache was computed.
+    pub stamp: Option<FileStamp>,
+}
+
+impl WatchedFile {
+    /// Return a human-readable description of how the file changed, if it did.
+    pub fn check_changed(&self) -> Result<Option<String>, Error> {
+        let path = self.path.display();
+        match self.stamp {
+            None if self.path.exists() => Ok(Some(format!("{path} was created"))),
+            None => Ok(None),
+            Some(_) if !self.path.exists() => Ok(Some(format!("{path} was removed"))),
+            Some(ref stamp) if !stamp.matches(&self.path)? => {
+                Ok(Some(format!("{path} has changed")))
+            }
+            Some(_) => Ok(None),
+        }
+    }
+}
+
+fn get_mtime(metadata: &std::fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos())
+}
+
+/// Metadata about how an env cache was computed, used to detect stale caches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheMeta {
+    pub envrc_path: PathBuf,
+    pub source: EnvSource,
+    /// Time the cache was computed, in seconds since the unix epoch.
+    pub created: u64,
+    pub envrc: FileStamp,
+    /// The shell and prelude an .envrc was evaluated with. Empty for other sources.
+    pub shell: String,
+    pub prelude: String,
+    pub watches: Vec<WatchedFile>,
+    /// In layered mode, the content hash of the parent layer's cache this one was computed on top
+    /// of.
+    pub parent: Option<String>,
+}
+
+impl CacheMeta {
+    /// Return human-readable reasons why the cache no longer reflects the .envrc of `layer`. An
+    /// empty list means the cache is fresh.
+    pub fn stale_reasons(
+        &self,
+        layer: &EnvrcLayer,
+        parent: Option<&EnvrcLayer>,
+    ) -> Result<Vec<String>, Error> {
+        let mut reasons = Vec::new();
+
+        let envrc_changed = !self.envrc.matches(&layer.envrc_path)?;
+        if envrc_changed {
+            reasons.push(format!("{} has changed", layer.envrc_path.display()));
+        }
+
+        if let (Some(ref expected), Some(parent)) = (&self.parent, parent) {
+            let actual = FileStamp::from_path(&parent.env_cache_path)?.map(|stamp| stamp.hash);
+            if actual.as_ref() != Some(expected) {
+                reasons.push(format!("{} was reloaded", parent.envrc_path.display()));
+            }
+        }
+
+        // The shell and prelude only apply to .envrc. If it has changed, its shebang may have too,
+        // but the cache is stale either way.
+        if self.source == EnvSource::Envrc && !envrc_changed {
+            let shell = Shell::for_envrc_path(&layer.envrc_path)?;
+            if self.shell != shell.program.display().to_string() {
+                reasons.push("FASTENV_SHELL has changed".to_owned());
+            } else if !prelude_matches(&shell, &self.prelude) {
+                reasons.push("FASTENV_PRELUDE or FASTENV_STDLIB has changed".to_owned());
+            }
+        }
+
+        for watch in &self.watches {
+            if let Some(reason) = watch.check_changed()? {
+                reasons.push(reason);
+            }
+        }
+
+        Ok(reasons)
+    }
+}
+
+/// Variables holding colon-separated lists of paths. Changes to these are stored as edits relative
+/// to the value before .envrc ran, such that they compose with whatever value the shim is later
+/// invoked with.
+pub const PATH_LIKE_VARS: &[&str] = &[
+    "PATH",
+    "MANPATH",
+    "INFOPATH",
+    "PYTHONPATH",
+    "LD_LIBRARY_PATH",
+    "DYLD_LIBRARY_PATH",
+    "LIBRARY_PATH",
+    "CPATH",
+    "PKG_CONFIG_PATH",
+    "NODE_PATH",
+    "PERL5LIB",
+    "GOPATH",
+    "CLASSPATH",
+    "XDG_DATA_DIRS",
+    "XDG_CONFIG_DIRS",
+];
+
+pub fn is_path_like(key: &OsStr) -> bool {
+    PATH_LIKE_VARS.iter().any(|var| OsStr::new(var) == key)
+}
+
+pub fn split_path_var(value: &OsStr) -> Vec<OsString> {
+    if value.is_empty() {
+        return Vec::new();
+    }
+
+    value
+        .as_bytes()
+        .split(|&x| x == b':')
+        .map(|entry| OsString::from_vec(entry.to_owned()))
+        .collect()
+}
+
+pub fn join_path_var(entries: &[OsString]) -> OsString {
+    let mut rv = OsString::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            rv.push(":");
+        }
+        rv.push(entry);
+    }
+    rv
+}
+
+/// Changes made to a path-like variable, relative to its previous value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathEdit {
+    pub prepend: Vec<OsString>,
+    pub append: Vec<OsString>,
+    pub remove: Vec<OsString>,
+}
+
+impl PathEdit {
+    /// Describe `new` as entries prepended to, appended to or removed from `old`. Returns `None` if
+    /// the change cannot be expressed that way, for example if entries were reordered.
+    pub fn compute(old: &OsStr, new: &OsStr) -> Option<Self> {
+        let old = split_path_var(old);
+        let new = split_path_var(new);
+
+        let mut remove = Vec::new();
+        for entry in &old {
+            if !new.contains(entry) && !remove.contains(entry) {
+                remove.push(entry.clone());
+            }
+        }
+
+        let kept: Vec<_> = old.iter().filter(|x| !remove.contains(x)).collect();
+        if kept.is_empty() {
+            return None;
+        }
+
+        let start = new
+            .windows(kept.len())
+            .position(|window| window.iter().eq(kept.iter().copied()))?;
+
+        Some(PathEdit {
+            prepend: new[..start].to_vec(),
+            append: new[start + kept.len()..].to_vec(),
+            remove,
+        })
+    }
+
+    /// Replay the edit against the current value of the variable.
+    ///
+    /// Entries that are about to be prepended or appended are removed from the current value
+    /// first, so that applying the edit to a value that already contains them does not produce
+    /// duplicates.
+    pub fn apply(&self, current: Option<&OsStr>) -> OsString {
+        let mut entries = self.prepend.clone();
+        entries.extend(
+            split_path_var(current.unwrap_or_default())
+                .into_iter()
+                .filter(|entry| {
+                    !self.remove.contains(entry)
+                        && !self.prepend.contains(entry)
+                        && !self.append.contains(entry)
+                }),
+        );
+        entries.extend(self.append.iter().cloned());
+        join_path_var(&entries)
     }
 }
 
+/// The contents of an env cache file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvCache {
+    /// `None` for caches written by versions of fastenv that did not record any metadata.
+    pub meta: Option<CacheMeta>,
+    /// Variables set to an absolute value.
+    pub env: Env,
+    /// Path-like variables changed relative to their previous value.
+    pub paths: BTreeMap<OsString, PathEdit>,
+    /// Variables the .envrc removed from the environment.
+    pub unset: BTreeSet<OsString>,
+    /// In layered mode, the cache of the parent layer, which this one applies on top of. Not part
+    /// of the cache file itself.
+    pub parent: Option<Box<EnvCache>>,
+}
+
+impl EnvCache {
+    /// Compute the variables to set, given a function to look up the current value of a variable.
+    pub fn resolve(&self, current: impl Fn(&OsStr) -> Option<OsString>) -> Env {
+        self.resolve_layers(&current)
+    }
+
+    fn resolve_layers(&self, current: &dyn Fn(&OsStr) -> Option<OsString>) -> Env {
+        let parent = match self.parent {
+            Some(ref parent) => parent,
+            None => {
+                let mut env = self.env.clone();
+                for (key, edit) in &self.paths {
+                    env.insert(key.clone(), edit.apply(current(key).as_deref()));
+                }
+                return env;
+            }
+        };
+
+        let mut env = parent.resolve_layers(current);
+        let parent_unset = parent.unset_vars();
+        let lookup = |key: &OsStr| match env.get(key) {
+            Some(value) => Some(value.clone()),
+            None if parent_unset.contains(key) => None,
+            None => current(key),
+        };
+        let paths = self
+            .paths
+            .iter()
+            .map(|(key, edit)| (key.clone(), edit.apply(lookup(key).as_deref())))
+            .collect::<Vec<_>>();
+
+        env.retain(|key, _| !self.unset.contains(key));
+        env.extend(self.env.clone());
+        env.extend(paths);
+        env
+    }
+
+    /// Variables to remove from the environment, including those removed by parent layers and not
+    /// set again.
+    pub fn unset_vars(&self) -> BTreeSet<OsString> {
+        let mut unset = match self.parent {
+            Some(ref parent) => parent.unset_vars(),
+            None => BTreeSet::new(),
+        };
+        unset.retain(|key| !self.env.contains_key(key) && !self.paths.contains_key(key));
+        unset.extend(self.unset.iter().cloned());
+        unset
+    }
+
+    /// Return human-readable reasons why the cache no longer reflects the current .envrc files. An
+    /// empty list means the cache is fresh.
+    pub fn stale_reasons(&self, ctx: &EnvrcContext) -> Result<Vec<String>, Error> {
+        let mut reasons = self.layer_stale_reasons(&ctx.layers())?;
+        let mut seen = BTreeSet::new();
+        reasons.retain(|reason| seen.insert(reason.clone()));
+        Ok(reasons)
+    }
+
+    fn layer_stale_reasons(&self, layers: &[EnvrcLayer]) -> Result<Vec<String>, Error> {
+        let (layer, parents) = layers.split_last().expect("no layers");
+        let mut reasons = match self.parent {
+            Some(ref parent) if !parents.is_empty() => parent.layer_stale_reasons(parents)?,
+            _ => Vec::new(),
+        };
+
+        match self.meta {
+            Some(ref meta) => reasons.extend(meta.stale_reasons(layer, parents.last())?),
+            None => reasons.push("the cache was created by an older version of fastenv".to_owned()),
+        }
+        Ok(reasons)
+    }
+
+    /// Compute the variables to set, relative to the environment of the current process.
+    pub fn resolve_current(&self) -> Env {
+        self.resolve(|key| std::env::var_os(key))
+    }
+}
+
+// The cache file is a sequence of NUL-terminated records, each of which is a tag, a space, and a
+// tag-specific payload. Neither paths nor environment variables can contain NUL bytes, so no
+// escaping is necessary. The first record is the magic string followed by the format version.
+// Readers skip records with unknown tags.
+const CACHE_MAGIC: &[u8] = b"fastenv-env-cache";
+const CACHE_VERSION: u32 = 2;
+
+fn write_record(writer: &mut impl Write, tag: &[u8], payload: &[u8]) -> io::Result<()> {
+    writer.write_all(tag)?;
+    writer.write_all(b" ")?;
+    writer.write_all(payload)?;
+    writer.write_all(b"\0")
+}
+
+fn format_stamp(stamp: &FileStamp) -> String {
+    format!("{} {} {}", stamp.mtime, stamp.size, stamp.hash)
+}
+
+fn parse_stamp<'a>(parts: &mut impl Iterator<Item = &'a [u8]>) -> Option<FileStamp> {
+    let mut next_str = || std::str::from_utf8(parts.next()?).ok();
+    Some(FileStamp {
+        mtime: next_str()?.parse().ok()?,
+        size: next_str()?.parse().ok()?,
+        hash: next_str()?.to_owned(),
+    })
+}
+
+pub fn write_env_cache(writer: &mut impl Write, cache: &EnvCache) -> io::Result<()> {
+    write_record(writer, CACHE_MAGIC, CACHE_VERSION.to_string().as_bytes())?;
+
+    if let Some(ref meta) = cache.meta {
+        write_record(writer, b"envrc", meta.envrc_path.as_os_str().as_bytes())?;
+        write_record(writer, b"source", meta.source.as_str().as_bytes())?;
+        write_record(writer, b"created", meta.created.to_string().as_bytes())?;
+        write_record(writer, b"envrc-stamp", format_stamp(&meta.envrc).as_bytes())?;
+        write_record(writer, b"shell", meta.shell.as_bytes())?;
+        write_record(writer, b"prelude", meta.prelude.as_bytes())?;
+        if let Some(ref parent) = meta.parent {
+            write_record(writer, b"parent", parent.as_bytes())?;
+        }
+        for watch in &meta.watches {
+            let mut payload = match watch.stamp {
+                Some(ref stamp) => format_stamp(stamp).into_bytes(),
+                None => b"-".to_vec(),
+            };
+            payload.push(b' ');
+            payload.extend_from_slice(watch.path.as_os_str().as_bytes());
+            write_record(writer, b"watch", &payload)?;
+        }
+    }
+
+    for (key, value) in &cache.env {
+        let mut payload = key.as_bytes().to_owned();
+        payload.push(b'=');
+        payload.extend_from_slice(value.as_bytes());
+        write_record(writer, b"set", &payload)?;
+    }
+
+    for (key, edit) in &cache.paths {
+        for (tag, entries) in [
+            (&b"path-prepend"[..], &edit.prepend),
+            (&b"path-append"[..], &edit.append),
+            (&b"path-remove"[..], &edit.remove),
+        ] {
+            for entry in entries {
+                let mut payload = key.as_bytes().to_owned();
+                payload.push(b'=');
+                payload.extend_from_slice(entry.as_bytes());
+                write_record(writer, tag, &payload)?;
+            }
+        }
+    }
+
+    for key in &cache.unset {
+        write_record(writer, b"unset", key.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Parse an env cache. `path` is only used for error messages.
+pub fn parse_env_cache(contents: &[u8], path: &Path) -> Result<EnvCache, Error> {
+    let records = match contents.strip_prefix(CACHE_MAGIC) {
+        Some(rest) if rest.starts_with(b" ") => rest,
+        // caches written before the format was versioned are plain KEY=value lines
+        _ => {
+            return Ok(EnvCache {
+                meta: None,
+                env: read_env_lines(contents, path)?,
+                paths: BTreeMap::new(),
+                unset: BTreeSet::new(),
+                parent: None,
+            })
+        }
+    };
+
+    let corrupt = || Error::CorruptCache(path.to_owned());
+    let mut records = records.split(|&x| x == b'\0');
+
+    let version = records
+        .next()
+        .and_then(|header| std::str::from_utf8(&header[1..]).ok())
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or_else(corrupt)?;
+    if version > CACHE_VERSION {
+        return Err(Error::UnsupportedCacheVersion(path.to_owned()));
+    }
+
+    let mut envrc_path = None;
+    // caches written before other sources were supported do not record the source
+    let mut source = EnvSource::Envrc;
+    let mut created = None;
+    let mut envrc = None;
+    // caches written before other shells were supported were all evaluated with bash
+    let mut shell = "bash".to_owned();
+    let mut prelude = None;
+    let mut parent = None;
+    let mut watches = Vec::new();
+    let mut env = BTreeMap::new();
+    let mut paths = BTreeMap::<OsString, PathEdit>::new();
+    let mut unset = BTreeSet::new();
+
+    for record in records {
+        if record.is_empty() {
+            continue;
+        }
+
+        let (tag, payload) = match record.iter().position(|&x| x == b' ') {
+            Some(i) => (&record[..i], &record[i + 1..]),
+            None => (record, &b""[..]),
+        };
+
+        match tag {
+            b"envrc" => envrc_path = Some(PathBuf::from(OsStr::from_bytes(payload))),
+            b"source" => {
+                source = std::str::from_utf8(payload)
+                    .ok()
+                    .and_then(EnvSource::from_str)
+                    .ok_or_else(corrupt)?;
+            }
+            b"created" => {
+                created = std::str::from_utf8(payload)
+                    .ok()
+                    .and_then(|x| x.parse().ok());
+            }
+            b"envrc-stamp" => envrc = parse_stamp(&mut payload.split(|&x| x == b' ')),
+            b"shell" => shell = String::from_utf8_lossy(payload).into_owned(),
+            b"prelude" => prelude = Some(String::from_utf8_lossy(payload).into_owned()),
+            b"parent" => parent = Some(String::from_utf8_lossy(payload).into_owned()),
+            b"watch" => {
+                let watch = if let Some(path) = payload.strip_prefix(b"- ") {
+                    WatchedFile {
+                        path: PathBuf::from(OsStr::from_bytes(path)),
+                        stamp: None,
+                    }
+                } else {
+                    let mut parts = payload.splitn(4, |&x| x == b' ');
+                    let stamp = parse_stamp(&mut parts).ok_or_else(corrupt)?;
+                    let path = parts.next().ok_or_else(corrupt)?;
+                    WatchedFile {
+                        path: PathBuf::from(OsStr::from_bytes(path)),
+                        stamp: Some(stamp),
+                    }
+                };
+                watches.push(watch);
+            }
+            b"set" | b"path-prepend" | b"path-append" | b"path-remove" => {
+                let i = payload
+                    .iter()
+                    .position(|&x| x == b'=')
+                    .ok_or_else(corrupt)?;
+                let key = OsString::from_vec(payload[..i].to_owned());
+                let value = OsString::from_vec(payload[i + 1..].to_owned());
+                match tag {
+                    b"set" => {
+                        env.insert(key, value);
+                    }
+                    b"path-prepend" => paths.entry(key).or_default().prepend.push(value),
+                    b"path-append" => paths.entry(key).or_default().append.push(value),
+                    _ => paths.entry(key).or_default().remove.push(value),
+                }
+            }
+            b"unset" => {
+                unset.insert(OsString::from_vec(payload.to_owned()));
+            }
+            _ => log::debug!(
+                "ignoring unknown record {:?} in env cache",
+                String::from_utf8_lossy(tag)
+            ),
+        }
+    }
+
+    let meta = match (envrc_path, created, envrc, prelude) {
+        (Some(envrc_path), Some(created), Some(envrc), Some(prelude)) => Some(CacheMeta {
+            envrc_path,
+            source,
+            created,
+            envrc,
+            shell,
+            prelude,
+            watches,
+            parent,
+        }),
+        _ => None,
+    };
+
+    Ok(EnvCache {
+        meta,
+        env,
+        paths,
+        unset,
+        parent: None,
+    })
+}
+
 pub fn parse_env_line(line: &[u8], env: &mut Env, prev_var_name: &mut Option<OsString>) {
     let mut split_iter = line.splitn(2, |&x| x == b'=');
 
@@ -85,25 +997,216 @@ pub fn parse_env_line(line: &[u8], env: &mut Env, prev_var_name: &mut Option<OsS
     }
 }
 
-pub fn get_envvars(ctx: &EnvrcContext) -> Result<Option<Env>, Error> {
-    if let Ok(file) = std::fs::File::open(&ctx.env_cache_path) {
-        let mut loaded_env_cache = BTreeMap::new();
-        let reader = BufReader::new(file);
-
-        let mut prev_var_name = None;
+fn read_env_lines<R: BufRead>(reader: R, path: &Path) -> Result<Env, Error> {
+    let mut env = BTreeMap::new();
+    let mut prev_var_name = None;
 
-        for line in reader.split(b'\n') {
-            let raw_line = line?;
-            let mut line = raw_line.as_slice();
-            while let Some(b'\n') = line.last() {
-                line = &line[..line.len()];
-            }
+    for line in reader.split(b'\n') {
+        let raw_line = line?;
+        let mut line = raw_line.as_slice();
+        while let Some(b'\n') = line.last() {
+            line = &line[..line.len()];
+        }
 
-            parse_env_line(line, &mut loaded_env_cache, &mut prev_var_name);
+        // a value continued from a variable that was never started, so this is not an env cache
+        if prev_var_name.is_none() && !line.contains(&b'=') {
+            return Err(Error::CorruptCache(path.to_owned()));
         }
 
-        return Ok(Some(loaded_env_cache));
+        parse_env_line(line, &mut env, &mut prev_var_name);
     }
 
-    Ok(None)
+    Ok(env)
+}
+
+/// Read the cache of a single layer, without its parents.
+pub fn read_layer_cache(layer: &EnvrcLayer) -> Result<Option<EnvCache>, Error> {
+    let mut contents = Vec::new();
+    match std::fs::File::open(&layer.env_cache_path) {
+        Ok(mut file) => file.read_to_end(&mut contents)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(parse_env_cache(&contents, &layer.env_cache_path)?))
+}
+
+/// Read the cache of the nearest .envrc, with the caches of all parent layers attached. Returns
+/// `None` if any layer has not been loaded yet.
+pub fn read_env_cache(ctx: &EnvrcContext) -> Result<Option<EnvCache>, Error> {
+    let mut rv: Option<EnvCache> = None;
+    for layer in ctx.layers() {
+        let mut cache = match read_layer_cache(&layer)? {
+            Some(cache) => cache,
+            None => return Ok(None),
+        };
+        cache.parent = rv.map(Box::new);
+        rv = Some(cache);
+    }
+
+    Ok(rv)
+}
+
+pub fn get_envvars(ctx: &EnvrcContext) -> Result<Option<Env>, Error> {
+    Ok(read_env_cache(ctx)?.map(|cache| cache.resolve_current()))
+}
+
+#[test]
+fn test_path_edit() {
+    let edit = PathEdit::compute(
+        OsStr::new("/usr/local/bin:/usr/bin:/bin"),
+        OsStr::new("/project/bin:/usr/bin:/bin:/opt/bin"),
+    )
+    .unwrap();
+    assert_eq!(
+        edit,
+        PathEdit {
+            prepend: vec!["/project/bin".into()],
+            append: vec!["/opt/bin".into()],
+            remove: vec!["/usr/local/bin".into()],
+        }
+    );
+
+    // a different caller PATH keeps its own entries
+    assert_eq!(
+        edit.apply(Some(OsStr::new("/home/user/bin:/usr/local/bin:/usr/bin"))),
+        "/project/bin:/home/user/bin:/usr/bin:/opt/bin"
+    );
+
+    // applying twice does not duplicate entries
+    assert_eq!(
+        edit.apply(Some(OsStr::new("/project/bin:/usr/bin:/opt/bin"))),
+        "/project/bin:/usr/bin:/opt/bin"
+    );
+
+    assert_eq!(edit.apply(None), "/project/bin:/opt/bin");
+
+    // reordering cannot be expressed as an edit
+    assert_eq!(
+        PathEdit::compute(OsStr::new("/a:/b"), OsStr::new("/b:/a")),
+        None
+    );
+
+    // neither can replacing every entry
+    assert_eq!(PathEdit::compute(OsStr::new("/a"), OsStr::new("/b")), None);
+}
+
+#[test]
+fn test_env_cache_roundtrip() {
+    let cache = EnvCache {
+        meta: Some(CacheMeta {
+            envrc_path: PathBuf::from("/project/.envrc"),
+            source: EnvSource::Dotenv,
+            created: 1700000000,
+            envrc: FileStamp {
+                mtime: 1700000000123456789,
+                size: 42,
+                hash: "abcd".to_owned(),
+            },
+            shell: "/bin/bash".to_owned(),
+            prelude: "eval \"$(direnv stdlib)\"\nFOO=bar".to_owned(),
+            watches: vec![
+                WatchedFile {
+                    path: PathBuf::from("/project/.env file"),
+                    stamp: Some(FileStamp {
+                        mtime: 1,
+                        size: 2,
+                        hash: "ef01".to_owned(),
+                    }),
+                },
+                WatchedFile {
+                    path: PathBuf::from("/project/.tool-versions"),
+                    stamp: None,
+                },
+            ],
+            parent: Some("1234".to_owned()),
+        }),
+        env: maplit::btreemap![
+            "PATH".into() => "/project/bin:/usr/bin".into(),
+            "MULTILINE".into() => "hello\nWORLD=1\n".into(),
+            "EMPTY".into() => "".into(),
+        ],
+        paths: maplit::btreemap![
+            "PATH".into() => PathEdit {
+                prepend: vec!["/project/bin".into(), "/project/node_modules/.bin".into()],
+                append: vec!["".into()],
+                remove: vec!["/usr/local/bin".into()],
+            },
+        ],
+        unset: maplit::btreeset!["PYTHONHOME".into(), "VIRTUAL_ENV".into()],
+        parent: None,
+    };
+
+    let mut buf = Vec::new();
+    write_env_cache(&mut buf, &cache).unwrap();
+    assert!(buf.starts_with(b"fastenv-env-cache 2\0"));
+    assert_eq!(parse_env_cache(&buf, Path::new("cache")).unwrap(), cache);
+}
+
+#[test]
+fn test_env_cache_legacy_format() {
+    let cache = parse_env_cache(b"FOO=bar\nMULTI=line1\nline2\n", Path::new("cache")).unwrap();
+    assert_eq!(cache.meta, None);
+    assert!(cache.unset.is_empty());
+    assert_eq!(
+        cache.env,
+        maplit::btreemap![
+            "FOO".into() => "bar".into(),
+            "MULTI".into() => "line1\nline2".into(),
+        ]
+    );
+
+    let err = parse_env_cache(b"garbage", Path::new("cache"));
+    assert!(matches!(err, Err(Error::CorruptCache(_))));
+}
+
+#[test]
+fn test_env_cache_newer_version() {
+    let err = parse_env_cache(b"fastenv-env-cache 999\0set FOO=bar\0", Path::new("cache"));
+    assert!(matches!(err, Err(Error::UnsupportedCacheVersion(_))));
+}
+
+#[test]
+fn test_resolve_layers() {
+    let root = EnvCache {
+        meta: None,
+        env: maplit::btreemap!["ROOT".into() => "1".into(), "SHARED".into() => "root".into()],
+        paths: maplit::btreemap![
+            "PATH".into() => PathEdit {
+                prepend: vec!["/repo/bin".into()],
+                ..PathEdit::default()
+            },
+        ],
+        unset: maplit::btreeset!["PYTHONHOME".into(), "VIRTUAL_ENV".into()],
+        parent: None,
+    };
+    let child = EnvCache {
+        meta: None,
+        env: maplit::btreemap!["SHARED".into() => "child".into(), "VIRTUAL_ENV".into() => "/venv".into()],
+        paths: maplit::btreemap![
+            "PATH".into() => PathEdit {
+                prepend: vec!["/repo/service/bin".into()],
+                ..PathEdit::default()
+            },
+        ],
+        unset: maplit::btreeset!["ROOT".into()],
+        parent: Some(Box::new(root)),
+    };
+
+    let env = child.resolve(|key| match key.to_str() {
+        Some("PATH") => Some("/usr/bin".into()),
+        _ => None,
+    });
+    assert_eq!(
+        env,
+        maplit::btreemap![
+            "PATH".into() => "/repo/service/bin:/repo/bin:/usr/bin".into(),
+            "SHARED".into() => "child".into(),
+            "VIRTUAL_ENV".into() => "/venv".into(),
+        ]
+    );
+    assert_eq!(
+        child.unset_vars(),
+        maplit::btreeset!["PYTHONHOME".into(), "ROOT".into()]
+    );
 }
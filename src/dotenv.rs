@@ -0,0 +1,292 @@
+//! A parser for .env files, following the dialect most dotenv implementations agree on:
+//!
+//! * `KEY=value`, optionally prefixed with `export`
+//! * `#` comments, both on their own line and after unquoted values
+//! * single-quoted values are taken literally
+//! * double-quoted values support backslash escapes and variable expansion
+//! * unquoted values are trimmed and support variable expansion
+//! * quoted values may span multiple lines
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("line {0}: expected KEY=value")]
+    InvalidLine(usize),
+    #[error("line {0}: unterminated quoted value")]
+    UnterminatedQuote(usize),
+}
+
+/// Parse the contents of a .env file into variables, in order of definition.
+///
+/// `$VAR`, `${VAR}` and `${VAR:-default}` are expanded using variables defined earlier in the
+/// file, and `lookup` for anything else.
+pub fn parse(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut parser = Parser {
+        chars: contents.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+
+    let get = |vars: &[(String, String)], name: &str| {
+        vars.iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| lookup(name))
+    };
+
+    loop {
+        parser.skip_while(char::is_whitespace);
+        match parser.peek() {
+            None => break,
+            Some('#') => {
+                parser.skip_while(|c| c != '\n');
+                continue;
+            }
+            Some(_) => (),
+        }
+
+        let line = parser.line;
+        let mut key = parser.take_while(is_key_char);
+        if key == "export" && parser.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            parser.skip_while(|c| c == ' ' || c == '\t');
+            key = parser.take_while(is_key_char);
+        }
+
+        if key.is_empty() {
+            return Err(Error::InvalidLine(line));
+        }
+
+        parser.skip_while(|c| c == ' ' || c == '\t');
+        if parser.next() != Some('=') {
+            return Err(Error::InvalidLine(line));
+        }
+        parser.skip_while(|c| c == ' ' || c == '\t');
+
+        let value = match parser.peek() {
+            Some('\'') => {
+                parser.next();
+                let value = parser.take_while(|c| c != '\'');
+                if parser.next() != Some('\'') {
+                    return Err(Error::UnterminatedQuote(line));
+                }
+                parser.skip_rest_of_line();
+                value
+            }
+            Some('"') => {
+                parser.next();
+                let mut raw = String::new();
+                loop {
+                    match parser.next() {
+                        None => return Err(Error::UnterminatedQuote(line)),
+                        Some('"') => break,
+                        // escapes are decoded along with the expansion, so only skip them here
+                        Some('\\') => match parser.next() {
+                            None => return Err(Error::UnterminatedQuote(line)),
+                            Some(c) => {
+                                raw.push('\\');
+                                raw.push(c);
+                            }
+                        },
+                        Some(c) => raw.push(c),
+                    }
+                }
+                parser.skip_rest_of_line();
+                expand_inner(&raw, true, |name| get(&vars, name))
+            }
+            _ => {
+                let mut raw = String::new();
+                while let Some(c) = parser.peek() {
+                    if c == '\n' || (c == '#' && raw.ends_with([' ', '\t'])) {
+                        break;
+                    }
+                    raw.push(c);
+                    parser.next();
+                }
+                parser.skip_rest_of_line();
+                expand(raw.trim_end(), |name| get(&vars, name))
+            }
+        };
+
+        vars.retain(|(existing, _)| *existing != key);
+        vars.push((key, value));
+    }
+
+    Ok(vars)
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Expand `$VAR`, `${VAR}` and `${VAR:-default}`. `\$` is a literal dollar sign.
+pub fn expand(raw: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    expand_inner(raw, false, lookup)
+}
+
+/// Like `expand`, but with `unescape` also decode the backslash escapes of double-quoted values.
+/// Doing both in one pass keeps `\\$FOO` (a backslash, then the value of FOO) apart from `\$FOO`.
+fn expand_inner(raw: &str, unescape: bool, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut rv = String::new();
+    let mut rest = raw;
+
+    while let Some(i) = rest.find(['$', '\\']) {
+        rv.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        if rest[i..].starts_with('\\') {
+            let mut chars = after.chars();
+            match chars.next() {
+                Some('$') => rv.push('$'),
+                Some('n') if unescape => rv.push('\n'),
+                Some('r') if unescape => rv.push('\r'),
+                Some('t') if unescape => rv.push('\t'),
+                Some(c) if unescape => rv.push(c),
+                _ => {
+                    rv.push('\\');
+                    rest = after;
+                    continue;
+                }
+            }
+            rest = chars.as_str();
+            continue;
+        }
+
+        if let Some(braced) = after.strip_prefix('{') {
+            if let Some(end) = braced.find('}') {
+                let expr = &braced[..end];
+                let (name, default) = match expr.split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (expr, None),
+                };
+                let value = lookup(name).filter(|value| !value.is_empty() || default.is_none());
+                rv.push_str(&value.or(default.map(str::to_owned)).unwrap_or_default());
+                rest = &braced[end + 1..];
+                continue;
+            }
+        }
+
+        let name_len = after
+            .find(|c: char| !is_name_char(c))
+            .unwrap_or(after.len());
+        if name_len == 0 {
+            rv.push('$');
+            rest = after;
+        } else {
+            rv.push_str(&lookup(&after[..name_len]).unwrap_or_default());
+            rest = &after[name_len..];
+        }
+    }
+
+    rv.push_str(rest);
+    rv
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut rv = String::new();
+        while let Some(c) = self.peek().filter(|&c| f(c)) {
+            rv.push(c);
+            self.next();
+        }
+        rv
+    }
+
+    fn skip_while(&mut self, f: impl Fn(char) -> bool) {
+        self.take_while(f);
+    }
+
+    fn skip_rest_of_line(&mut self) {
+        self.skip_while(|c| c != '\n');
+    }
+}
+
+#[test]
+fn test_parse() {
+    let input = r#"
+# a comment
+FOO=bar
+export SPACED = hello world  # trailing comment
+SINGLE='literal $FOO # not a comment'
+DOUBLE="expanded $FOO\nand ${SPACED}"
+MULTILINE="first
+second"
+ESCAPED="\$FOO \"quoted\""
+FROM_ENV=${HOME}/bin
+DEFAULT=${MISSING:-fallback}
+FOO=overridden
+"#;
+
+    let vars = parse(input, |name| match name {
+        "HOME" => Some("/home/user".to_owned()),
+        _ => None,
+    })
+    .unwrap();
+
+    let expected: Vec<(String, String)> = [
+        ("SPACED", "hello world"),
+        ("SINGLE", "literal $FOO # not a comment"),
+        ("DOUBLE", "expanded bar\nand hello world"),
+        ("MULTILINE", "first\nsecond"),
+        ("ESCAPED", "$FOO \"quoted\""),
+        ("FROM_ENV", "/home/user/bin"),
+        ("DEFAULT", "fallback"),
+        ("FOO", "overridden"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
+    assert_eq!(vars, expected);
+}
+
+#[test]
+fn test_parse_escaped_dollar() {
+    let lookup = |name: &str| Some(name.to_lowercase());
+    assert_eq!(
+        parse(r#"FOO="\\$BAR""#, lookup).unwrap(),
+        vec![("FOO".to_owned(), "\\bar".to_owned())]
+    );
+    assert_eq!(
+        parse(r#"FOO="\$BAR""#, lookup).unwrap(),
+        vec![("FOO".to_owned(), "$BAR".to_owned())]
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        parse("FOO=bar\nthis is not valid\n", |_| None),
+        Err(Error::InvalidLine(2))
+    );
+    assert_eq!(
+        parse("FOO='unterminated\n", |_| None),
+        Err(Error::UnterminatedQuote(1))
+    );
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeSet;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+
+use crate::core::{self, Env};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarsFormat {
+    /// Unquoted KEY=value lines. Values containing newlines cannot be told apart from the next
+    /// variable, so this is only meant for humans.
+    Raw,
+    /// export/unset statements for bash and other POSIX shells.
+    #[clap(alias = "posix")]
+    Bash,
+    /// export/unset statements for zsh.
+    Zsh,
+    /// set -gx/set -e statements for fish.
+    Fish,
+    /// $env assignments and hide-env statements for nushell.
+    Nushell,
+    /// $env: assignments and Remove-Item statements for PowerShell.
+    Powershell,
+    /// A JSON object, with unset variables as null.
+    Json,
+    /// KEY="value" lines as understood by most .env parsers. Unset variables are omitted.
+    Dotenv,
+    /// NUL-terminated KEY=value entries, like 'env -0'. Unset variables are omitted.
+    Nul,
+}
+
+/// Write the environment changes in the given format.
+pub fn write_vars(
+    w: &mut impl Write,
+    format: VarsFormat,
+    env: &Env,
+    unset: &BTreeSet<OsString>,
+) -> io::Result<()> {
+    if format == VarsFormat::Json {
+        let mut object = serde_json::Map::new();
+        for (k, v) in env {
+            object.insert(
+                k.to_string_lossy().into_owned(),
+                v.to_string_lossy().into_owned().into(),
+            );
+        }
+        for k in unset {
+            object.insert(k.to_string_lossy().into_owned(), serde_json::Value::Null);
+        }
+        serde_json::to_writer_pretty(&mut *w, &object)?;
+        return w.write_all(b"\n");
+    }
+
+    for (k, v) in env {
+        if !matches!(format, VarsFormat::Raw | VarsFormat::Nul) && !is_identifier(k) {
+            log::warn!("skipping variable with invalid name {:?}", k);
+            continue;
+        }
+
+        let k = k.as_bytes();
+        match format {
+            VarsFormat::Raw => {
+                w.write_all(k)?;
+                w.write_all(b"=")?;
+                w.write_all(v.as_bytes())?;
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Bash | VarsFormat::Zsh => {
+                w.write_all(b"export ")?;
+                w.write_all(k)?;
+                w.write_all(b"=")?;
+                w.write_all(&quote_posix(v.as_bytes()))?;
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Fish => {
+                // fish represents path-like variables as lists. Only those marked with --path are
+                // colon-joined when exported, otherwise XDG_DATA_DIRS and the like would reach
+                // child processes space-separated.
+                let entries = if core::is_path_like(OsStr::from_bytes(k)) {
+                    w.write_all(b"set -gx --path ")?;
+                    core::split_path_var(v)
+                } else {
+                    w.write_all(b"set -gx ")?;
+                    vec![v.clone()]
+                };
+                w.write_all(k)?;
+                for entry in entries {
+                    w.write_all(b" ")?;
+                    w.write_all(&quote_fish(entry.as_bytes()))?;
+                }
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Nushell => {
+                w.write_all(b"$env.")?;
+                w.write_all(k)?;
+                w.write_all(b" = ")?;
+                w.write_all(&quote_double(v.as_bytes(), false))?;
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Powershell => {
+                w.write_all(b"$env:")?;
+                w.write_all(k)?;
+                w.write_all(b" = ")?;
+                w.write_all(&quote_powershell(v.as_bytes()))?;
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Dotenv => {
+                w.write_all(k)?;
+                w.write_all(b"=")?;
+                w.write_all(&quote_double(v.as_bytes(), true))?;
+                w.write_all(b"\n")?;
+            }
+            VarsFormat::Nul => {
+                w.write_all(k)?;
+                w.write_all(b"=")?;
+                w.write_all(v.as_bytes())?;
+                w.write_all(b"\0")?;
+            }
+            VarsFormat::Json => unreachable!(),
+        }
+    }
+
+    for k in unset {
+        if !is_identifier(k) {
+            continue;
+        }
+
+        let statement: &[u8] = match format {
+            VarsFormat::Bash | VarsFormat::Zsh => b"unset ",
+            VarsFormat::Fish => b"set -e ",
+            VarsFormat::Nushell => b"hide-env -i ",
+            VarsFormat::Powershell => b"Remove-Item -ErrorAction SilentlyContinue Env:",
+            VarsFormat::Raw | VarsFormat::Dotenv | VarsFormat::Nul | VarsFormat::Json => continue,
+        };
+        w.write_all(statement)?;
+        w.write_all(k.as_bytes())?;
+        w.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn is_identifier(key: &OsStr) -> bool {
+    let key = key.as_bytes();
+    !key.is_empty()
+        && !key[0].is_ascii_digit()
+        && key.iter().all(|&x| x.is_ascii_alphanumeric() || x == b'_')
+}
+
+/// Single-quote a value for POSIX shells. Single quotes cannot be escaped within single quotes, so
+/// they are written as '\''.
+pub fn quote_posix(value: &[u8]) -> Vec<u8> {
+    let mut rv = vec![b'\''];
+    for &byte in value {
+        if byte == b'\'' {
+            rv.extend_from_slice(b"'\\''");
+        } else {
+            rv.push(byte);
+        }
+    }
+    rv.push(b'\'');
+    rv
+}
+
+fn quote_fish(value: &[u8]) -> Vec<u8> {
+    let mut rv = vec![b'\''];
+    for &byte in value {
+        if byte == b'\'' || byte == b'\\' {
+            rv.push(b'\\');
+        }
+        rv.push(byte);
+    }
+    rv.push(b'\'');
+    rv
+}
+
+fn quote_powershell(value: &[u8]) -> Vec<u8> {
+    let mut rv = vec![b'\''];
+    for &byte in value {
+        if byte == b'\'' {
+            rv.push(b'\'');
+        }
+        rv.push(byte);
+    }
+    rv.push(b'\'');
+    rv
+}
+
+/// Double-quote a value with backslash escapes, as understood by nushell and dotenv parsers.
+/// dotenv parsers additionally expand variables inside double quotes, so `$` has to be escaped
+/// there.
+fn quote_double(value: &[u8], escape_dollar: bool) -> Vec<u8> {
+    let mut rv = vec![b'"'];
+    for &byte in value {
+        match byte {
+            b'"' => rv.extend_from_slice(b"\\\""),
+            b'\\' => rv.extend_from_slice(b"\\\\"),
+            b'\n' => rv.extend_from_slice(b"\\n"),
+            b'\r' => rv.extend_from_slice(b"\\r"),
+            b'\t' => rv.extend_from_slice(b"\\t"),
+            b'$' if escape_dollar => rv.extend_from_slice(b"\\$"),
+            _ => rv.push(byte),
+        }
+    }
+    rv.push(b'"');
+    rv
+}
+
+#[test]
+fn test_write_vars() {
+    let env = maplit::btreemap![
+        "PATH".into() => "/a b:/c".into(),
+        "QUOTED".into() => "it's \"$HOME\"\nnext".into(),
+        "XDG_DATA_DIRS".into() => "/x:/y".into(),
+    ];
+    let unset = maplit::btreeset!["PYTHONHOME".into()];
+
+    let render = |format| {
+        let mut buf = Vec::new();
+        write_vars(&mut buf, format, &env, &unset).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+
+    assert_eq!(
+        render(VarsFormat::Bash),
+        "export PATH='/a b:/c'\nexport QUOTED='it'\\''s \"$HOME\"\nnext'\n\
+        export XDG_DATA_DIRS='/x:/y'\nunset PYTHONHOME\n"
+    );
+    assert_eq!(
+        render(VarsFormat::Fish),
+        "set -gx --path PATH '/a b' '/c'\nset -gx QUOTED 'it\\'s \"$HOME\"\nnext'\n\
+        set -gx --path XDG_DATA_DIRS '/x' '/y'\nset -e PYTHONHOME\n"
+    );
+    assert_eq!(
+        render(VarsFormat::Nushell),
+        "$env.PATH = \"/a b:/c\"\n$env.QUOTED = \"it's \\\"$HOME\\\"\\nnext\"\n\
+        $env.XDG_DATA_DIRS = \"/x:/y\"\nhide-env -i PYTHONHOME\n"
+    );
+    assert_eq!(
+        render(VarsFormat::Powershell),
+        "$env:PATH = '/a b:/c'\n$env:QUOTED = 'it''s \"$HOME\"\nnext'\n\
+        $env:XDG_DATA_DIRS = '/x:/y'\nRemove-Item -ErrorAction SilentlyContinue Env:PYTHONHOME\n"
+    );
+    assert_eq!(
+        render(VarsFormat::Dotenv),
+        "PATH=\"/a b:/c\"\nQUOTED=\"it's \\\"\\$HOME\\\"\\nnext\"\nXDG_DATA_DIRS=\"/x:/y\"\n"
+    );
+    assert_eq!(
+        render(VarsFormat::Nul),
+        "PATH=/a b:/c\0QUOTED=it's \"$HOME\"\nnext\0XDG_DATA_DIRS=/x:/y\0"
+    );
+    assert_eq!(
+        render(VarsFormat::Json),
+        "{\n  \"PATH\": \"/a b:/c\",\n  \"PYTHONHOME\": null,\n  \
+        \"QUOTED\": \"it's \\\"$HOME\\\"\\nnext\",\n  \"XDG_DATA_DIRS\": \"/x:/y\"\n}\n"
+    );
+}
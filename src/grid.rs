@@ -1,17 +1,30 @@
 use term_grid::{Direction, Filling, Grid, GridOptions};
 
 pub fn print_as_grid<T: AsRef<str>>(strings: &[T]) {
-    if print_as_grid_inner(strings).is_none() {
-        for string in strings {
-            eprintln!("{}", string.as_ref());
+    match format_as_grid(&console::Term::stderr(), strings) {
+        Some(grid) => eprint!("{grid}"),
+        None => {
+            for string in strings {
+                eprintln!("{}", string.as_ref());
+            }
         }
     }
 }
 
-fn print_as_grid_inner<T: AsRef<str>>(strings: &[T]) -> Option<()> {
-    let width = console::Term::stderr()
-        .size_checked()
-        .map(|(_rows, cols)| cols)?;
+/// Like `print_as_grid`, but for output that belongs on stdout.
+pub fn print_as_grid_stdout<T: AsRef<str>>(strings: &[T]) {
+    match format_as_grid(&console::Term::stdout(), strings) {
+        Some(grid) => print!("{grid}"),
+        None => {
+            for string in strings {
+                println!("{}", string.as_ref());
+            }
+        }
+    }
+}
+
+fn format_as_grid<T: AsRef<str>>(term: &console::Term, strings: &[T]) -> Option<String> {
+    let width = term.size_checked().map(|(_rows, cols)| cols)?;
 
     let grid = Grid::new(
         strings.iter().collect(),
@@ -22,6 +35,5 @@ fn print_as_grid_inner<T: AsRef<str>>(strings: &[T]) -> Option<()> {
         },
     );
 
-    eprint!("{grid}");
-    Some(())
+    Some(grid.to_string())
 }
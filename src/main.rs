@@ -1,9 +1,9 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use std::ffi::{OsStr, OsString};
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufWriter, Write};
 
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::symlink;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
@@ -16,10 +16,19 @@ use clap::Parser;
 use console::style;
 
 mod core;
+mod dotenv;
+mod export;
 mod grid;
+mod settings;
+mod shell;
+mod shims;
 mod signals;
+mod toml_env;
+mod tool_versions;
+mod trust;
 
 use crate::core::resolve_envrc_context;
+use crate::shell::Shell;
 
 // Disabling colored help because the after_help isn't colored, for consistency
 #[derive(Parser, Debug)]
@@ -28,12 +37,26 @@ use crate::core::resolve_envrc_context;
     about,
     disable_colored_help = true,
     after_help = "ENVIRONMENT VARIABLES:
-    QUICKENV_LOG=debug to enable debug output (in shim commands as well)
-    QUICKENV_LOG=error to silence everything but errors
-    QUICKENV_NO_SHIM=1 to disable loading of .envrc, and effectively disable shims
-    QUICKENV_SHIM_EXEC=1 to directly exec() shims instead of spawning them as subprocess. This can help with attaching debuggers.
-    QUICKENV_NO_SHIM_WARNINGS=1 to disable nags about running 'fastenv shim' everytime a new binary is added
-    QUICKENV_PRELUDE='eval \"$(direnv stdlib)\"' can be overridden to something else to get rid of the direnv stdlib and therefore direnv dependency, or to inject additional code before executing each envrc.
+    FASTENV_LOG=debug to enable debug output (in shim commands as well)
+    FASTENV_LOG=error to silence everything but errors
+    FASTENV_NO_SHIM=1 to disable loading of .envrc, and effectively disable shims
+    FASTENV_SHIM_EXEC=1 to directly exec() shims instead of spawning them as subprocess. This can help with attaching debuggers.
+    FASTENV_NO_SHIM_WARNINGS=1 to disable nags about running 'fastenv shim' everytime a new binary is added
+    FASTENV_STDLIB=auto|direnv|builtin selects the stdlib available to .envrc (PATH_add, dotenv, source_up, layout, ...). Defaults to auto, which uses direnv's stdlib if direnv is installed, and fastenv's built-in one otherwise.
+    FASTENV_SHELL=bash|zsh|sh|<path> selects the shell .envrc files are evaluated with, unless they start with a shebang line such as '#!/usr/bin/env zsh'. Defaults to bash. Shells other than bash get a reduced stdlib that only relies on POSIX sh.
+    FASTENV_PRELUDE='eval \"$(direnv stdlib)\"' can be set to override the stdlib entirely, or to inject additional code before executing each envrc.
+    FASTENV_SOURCES=toml,envrc,dotenv,tool-versions to choose which files define the environment. Defaults to envrc. In each directory, .fastenv.toml takes precedence over .envrc, then .env, then .tool-versions.
+    FASTENV_TOOLS_DIR=<dir> where tools listed in .tool-versions are installed, as <dir>/<tool>/<version>/bin. Defaults to $ASDF_DATA_DIR/installs or ~/.asdf/installs.
+    FASTENV_LAYERED=1 to evaluate every .envrc from the filesystem root down to the current directory, each on top of the previous one, instead of just the nearest one.
+    FASTENV_LAYERED_BOUNDARY=<dir> to only layer .envrc files within the given directory, such as the root of a monorepo.
+    FASTENV_STALE_CACHE=warn|error|ignore controls what shims do when .envrc has changed since the last 'fastenv reload'. Defaults to warn.
+    FASTENV_AUTO_RELOAD=1 to have shims reload the environment themselves when it has not been loaded yet or is stale, as long as the .envrc is allowed.
+    FASTENV_ENVRC_PREFIX=1 to prefix each line .envrc prints with [envrc]. Its output is always shown on stderr.
+    FASTENV_ENVRC_LOG=1 to also write the output of .envrc to a .log file next to its cache in ~/.fastenv/envs/.
+
+All of these can also be set in ~/.fastenv/config.toml or a project's .fastenv-config.toml using 'fastenv config set', by their name without the FASTENV_ prefix in lowercase, such as 'fastenv config set stale_cache error'.
+
+The QUICKENV_* names of earlier versions still work, but the FASTENV_* ones take precedence.
 "
 )]
 struct Args {
@@ -44,12 +67,54 @@ struct Args {
 #[derive(Parser, Debug)]
 enum Command {
     /// Execute .envrc in the current or parent directory, and cache the new variables.
+    ///
+    /// The .envrc has to be approved using 'fastenv allow' first.
     Reload,
+    /// Approve the contents of .envrc, so that 'fastenv reload' may execute it.
+    ///
+    /// The approval is tied to the current contents of the file. If it changes, it has to be
+    /// allowed again.
+    Allow {
+        /// The .envrc or directory containing it. Defaults to the .envrc in the current or any
+        /// parent directory.
+        path: Option<PathBuf>,
+    },
+    /// Refuse to execute .envrc, even if it was allowed before.
+    Deny {
+        /// The .envrc or directory containing it. Defaults to the .envrc in the current or any
+        /// parent directory.
+        path: Option<PathBuf>,
+    },
+    /// Forget whether .envrc was allowed or denied.
+    Revoke {
+        /// The .envrc or directory containing it. Defaults to the .envrc in the current or any
+        /// parent directory.
+        path: Option<PathBuf>,
+    },
+    /// Show which .envrc, env cache and shims apply in the current directory.
+    Status {
+        /// Print the status as JSON.
+        #[clap(long)]
+        json: bool,
+    },
     /// Dump out cached environment variables.
     ///
-    /// For example, use 'fastenv reload && eval "$(fastenv vars)"' to load the environment like
-    /// direnv normally would.
-    Vars,
+    /// For example, use 'fastenv reload && eval "$(fastenv vars --format bash)"' to load the
+    /// environment like direnv normally would.
+    Vars {
+        /// The output format. All formats except 'raw' can be safely loaded into the respective
+        /// shell or tool.
+        #[clap(long, value_enum, default_value = "raw")]
+        format: export::VarsFormat,
+    },
+    /// Show how the cached .envrc environment differs from the current shell.
+    ///
+    /// Path-like variables such as PATH are shown as individual entries being added or removed.
+    Diff {
+        /// When to color the output.
+        #[clap(long, value_enum, default_value = "auto")]
+        color: ColorChoice,
+    },
     /// Create a new shim binary in ~/.fastenv/bin/.
     ///
     /// Executing that binary will run in the context of the nearest .envrc, as if it was activated
@@ -63,21 +128,78 @@ enum Command {
         /// Disable confirmation prompts when running 'shim' without arguments.
         #[clap(long, short)]
         yes: bool,
+        /// Only apply .envrc files within this directory, and run the commands normally anywhere
+        /// else. Can be given multiple times. Shimming a command again without '--only-in' lifts
+        /// the restriction.
+        #[clap(long, value_name = "DIR")]
+        only_in: Vec<PathBuf>,
         /// The names of the commands to expose. If missing, fastenv will determine recommended
         /// commands itself and ask for confirmation.
         commands: Vec<String>,
     },
     /// Remove a shim binary from ~/.fastenv/bin/.
     Unshim {
+        /// Also remove all shims that were created in this directory or any directory within it.
+        #[clap(long, value_name = "DIR")]
+        project: Option<PathBuf>,
         /// The names of the commands to remove.
         commands: Vec<String>,
     },
+    /// List the shims in ~/.fastenv/bin/ and which binary each of them runs in the current
+    /// directory.
+    ///
+    /// Files in ~/.fastenv/bin/ that are not shims, and symlinks whose target no longer exists,
+    /// are listed separately.
+    List {
+        /// Also show where, when and how each shim was created.
+        #[clap(long, short)]
+        long: bool,
+        /// Print the list as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Remove env caches whose .envrc no longer exists.
+    Gc {
+        /// Only print which caches would be removed.
+        #[clap(long)]
+        dry_run: bool,
+        /// Also remove caches that have not been used for this long, such as '30d' or '12h'.
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+        older_than: Option<u64>,
+    },
+    /// Check the installation for common problems.
+    ///
+    /// Exits with a non-zero status if any check fails.
+    Doctor,
+    /// Read and change fastenv's settings.
+    ///
+    /// Settings are read from ~/.fastenv/config.toml and from the nearest .fastenv-config.toml in
+    /// the current or any parent directory, which takes precedence. Environment variables such as
+    /// FASTENV_STDLIB take precedence over both.
+    ///
+    /// Available settings are no_shim, shim_exec, no_shim_warnings, prelude, stdlib, shell,
+    /// stale_cache, sources, tools_dir, layered, layered_boundary, auto_reload, envrc_prefix,
+    /// envrc_log and log, which correspond to the FASTENV_* environment variables. prelude, shell
+    /// and tools_dir can only be set in the global config file.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
     /// Run a program with .envrc loaded without having to shim it.
     Exec {
         program_name: OsString,
         #[clap(allow_hyphen_values = true, trailing_var_arg = true)]
         args: Vec<OsString>,
     },
+    /// Print fastenv's built-in stdlib, which is available to .envrc when direnv is not installed.
+    Stdlib {
+        /// Print the reduced stdlib used for shells other than bash instead.
+        #[clap(long)]
+        posix: bool,
+    },
+    /// Print a .env file as bash export statements. Used by the built-in stdlib.
+    #[clap(hide = true)]
+    Dotenv { path: PathBuf },
     /// Determine which program fastenv's shim would launch under the hood.
     ///
     /// This will error if the shim is not installed. Pass '--pretend-shimmed' to simulate what would
@@ -93,6 +215,37 @@ enum Command {
     },
 }
 
+#[derive(Parser, Debug)]
+enum ConfigCommand {
+    /// Print the effective value of a setting. Exits with a non-zero status if it is not set.
+    Get { key: String },
+    /// Change a setting in ~/.fastenv/config.toml.
+    Set {
+        /// Change it in the nearest .fastenv-config.toml instead, creating one in the current
+        /// directory if there is none.
+        #[clap(long)]
+        project: bool,
+        key: String,
+        value: String,
+    },
+    /// Remove a setting from ~/.fastenv/config.toml.
+    Unset {
+        /// Remove it from the nearest .fastenv-config.toml instead.
+        #[clap(long)]
+        project: bool,
+        key: String,
+    },
+    /// Print all settings that are set, and where their values come from.
+    List,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
 fn main() {
     match main_inner() {
         Ok(()) => (),
@@ -104,7 +257,10 @@ fn main() {
 }
 
 fn main_inner() -> Result<(), Error> {
-    env_logger::Builder::new()
+    let settings = settings::load();
+
+    let mut logger = env_logger::Builder::new();
+    logger
         .format(|buf, record| match record.level() {
             Level::Info => writeln!(buf, "{}", record.args()),
             // We're adding "fastenv" to every line here on purpose, because it makes debugging
@@ -125,9 +281,43 @@ fn main_inner() -> Result<(), Error> {
                 record.args()
             ),
         })
-        .filter_level(LevelFilter::Info)
-        .parse_env("QUICKENV_LOG")
-        .init();
+        .filter_level(LevelFilter::Info);
+    match settings {
+        Ok((ref settings, _)) => {
+            if let Some(ref filters) = settings.log {
+                logger.parse_filters(filters);
+            }
+        }
+        // fall back to the environment variable alone, so that loading settings can be debugged
+        Err(_) => {
+            if let Some((filters, _)) = settings::env_var("FASTENV_LOG") {
+                logger.parse_filters(&filters);
+            }
+        }
+    }
+    logger.init();
+    settings::log_legacy_env_vars();
+
+    match settings {
+        Ok((settings, ignored)) => {
+            if let Some(e) = ignored {
+                log::warn!("ignoring project config: {}", e);
+            }
+            settings::init(settings);
+        }
+        // 'fastenv config' and 'fastenv doctor' are how invalid settings get fixed, so they run
+        // with the defaults instead. So does anything clap will reject or answer with help.
+        Err(e)
+            if !invoked_as_shim()
+                && !Args::try_parse().is_ok_and(|args| {
+                    !matches!(args.subcommand, Command::Config { .. } | Command::Doctor)
+                }) =>
+        {
+            log::debug!("ignoring invalid settings: {}", e);
+            settings::init(settings::Settings::default());
+        }
+        Err(e) => return Err(e.into()),
+    }
 
     check_for_shim().context("failed to run shimmed command")?;
 
@@ -137,10 +327,28 @@ fn main_inner() -> Result<(), Error> {
 
     match args.subcommand {
         Command::Reload => command_reload(),
-        Command::Vars => command_vars(),
-        Command::Shim { commands, yes } => command_shim(commands, yes),
-        Command::Unshim { commands } => command_unshim(commands),
+        Command::Allow { path } => command_allow(path),
+        Command::Deny { path } => command_deny(path),
+        Command::Revoke { path } => command_revoke(path),
+        Command::Status { json } => command_status(json),
+        Command::Vars { format } => command_vars(format),
+        Command::Diff { color } => command_diff(color),
+        Command::Shim {
+            commands,
+            yes,
+            only_in,
+        } => command_shim(commands, yes, only_in),
+        Command::Unshim { project, commands } => command_unshim(commands, project),
+        Command::List { long, json } => command_list(json, long),
+        Command::Gc {
+            dry_run,
+            older_than,
+        } => command_gc(dry_run, older_than),
+        Command::Doctor => command_doctor(),
+        Command::Config { command } => command_config(command),
         Command::Exec { program_name, args } => command_exec(program_name, args),
+        Command::Stdlib { posix } => command_stdlib(posix),
+        Command::Dotenv { path } => command_dotenv(&path),
         Command::Which {
             program_name,
             pretend_shimmed,
@@ -148,178 +356,213 @@ fn main_inner() -> Result<(), Error> {
     }
 }
 
-#[derive(Clone, Copy)]
-enum ParseState {
-    PreBefore,
-    InBefore,
-    PreAfter,
-    InAfter,
-    End,
+/// Read a NUL-separated environment as printed by `env -0`.
+fn parse_env_dump(contents: &[u8]) -> core::Env {
+    contents
+        .split(|&x| x == b'\0')
+        .filter_map(|entry| {
+            let i = entry.iter().position(|&x| x == b'=')?;
+            Some((
+                OsString::from_vec(entry[..i].to_owned()),
+                OsString::from_vec(entry[i + 1..].to_owned()),
+            ))
+        })
+        .collect()
 }
 
-fn parse_env_diff<R: BufRead>(
-    reader: R,
-    mut script_output: impl FnMut(&[u8]) -> Result<(), Error>,
-) -> Result<(core::Env, core::Env), Error> {
-    let mut parse_state = ParseState::PreBefore;
-    let mut old_env = BTreeMap::new();
-    let mut new_env = BTreeMap::new();
-    let mut prev_var_name = None;
-
-    for line in reader.split(b'\n') {
-        let raw_line = line?;
-        let mut line = raw_line.as_slice();
-        while let Some(b'\n') = line.last() {
-            line = &line[..line.len()];
+/// Read what the script produced by `Shell::wrap_envrc` captured in `capture_dir`: the environment
+/// before and after the .envrc ran, and the files it watches.
+fn read_env_capture(capture_dir: &Path) -> Result<LayerResult, Error> {
+    let read = |name: &str, required: bool| -> Result<Vec<u8>, Error> {
+        let path = capture_dir.join(name);
+        match std::fs::read(&path) {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Vec::new()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+                ".envrc exited before fastenv could read the resulting environment"
+            )),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
         }
+    };
 
-        match (parse_state, line) {
-            (ParseState::PreBefore, b"// BEGIN QUICKENV-BEFORE") => {
-                prev_var_name = None;
-                parse_state = ParseState::InBefore;
-            }
-            (ParseState::InBefore, b"// END QUICKENV-BEFORE") => {
-                prev_var_name = None;
-                parse_state = ParseState::PreAfter;
-            }
-            (ParseState::PreAfter, b"// BEGIN QUICKENV-AFTER") => {
-                prev_var_name = None;
-                parse_state = ParseState::InAfter;
-            }
-            (ParseState::InAfter, b"// END QUICKENV-AFTER") => {
-                prev_var_name = None;
-                parse_state = ParseState::End;
-            }
-            (ParseState::InBefore, line) => {
-                core::parse_env_line(line, &mut old_env, &mut prev_var_name);
-            }
-            (ParseState::InAfter, line) => {
-                core::parse_env_line(line, &mut new_env, &mut prev_var_name);
-            }
-            (_, _) => {
-                script_output(&raw_line)?;
-            }
+    let old_env = parse_env_dump(&read("before", true)?);
+    let new_env = parse_env_dump(&read("after", true)?);
+
+    let mut watches = Vec::new();
+    for path in read("watches", false)?.split(|&x| x == b'\0') {
+        let path = PathBuf::from(OsStr::from_bytes(path));
+        if !path.as_os_str().is_empty() && !watches.contains(&path) {
+            watches.push(path);
         }
     }
 
-    Ok((old_env, new_env))
+    Ok((old_env, new_env, watches))
 }
 
 #[test]
-fn test_parse_env_diff() {
-    let input = br#"
-some output 1
-// BEGIN QUICKENV-BEFORE
-hello=world
-bogus=wogus
-// END QUICKENV-BEFORE
-some output 2
-// BEGIN QUICKENV-AFTER
-hello=world
-bogus=wogus
-2
-more=keys
-// END QUICKENV-AFTER
-some output 3
-"#;
-
-    let mut output: Vec<Vec<u8>> = Vec::new();
-    let (old_env, new_env) = parse_env_diff(input.as_slice(), |line| {
-        output.push(line.to_owned());
-        Ok(())
-    })
-    .unwrap();
+fn test_parse_env_dump() {
+    let env = parse_env_dump(b"hello=world\0TRICKY=line\n// END FASTENV-AFTER\nFOO=bar\0EMPTY=\0");
     assert_eq!(
-        old_env,
+        env,
         maplit::btreemap![
             "hello".into() => "world".into(),
-            "bogus".into() => "wogus".into(),
-        ]
-    );
-
-    assert_eq!(
-        new_env,
-        maplit::btreemap![
-            "hello".into() => "world".into(),
-            "bogus".into() => "wogus\n2".into(),
-            "more".into() => "keys".into(),
-        ]
-    );
-
-    assert_eq!(
-        output,
-        vec![
-            b"".as_slice().to_owned(),
-            b"some output 1".as_slice().to_owned(),
-            b"some output 2".as_slice().to_owned(),
-            b"some output 3".as_slice().to_owned()
+            "TRICKY".into() => "line\n// END FASTENV-AFTER\nFOO=bar".into(),
+            "EMPTY".into() => "".into(),
         ]
     );
 }
 
-fn compute_envvars(fastenv_home: &Path) -> Result<(), Error> {
-    let mut ctx = crate::core::resolve_envrc_context(fastenv_home)?;
+/// Evaluate all layers of `ctx` and write their caches. The caller has to hold the locks from
+/// `core::lock_env_cache`. Anything the .envrc files print is shown on stderr, see
+/// `forward_envrc_output`. With `from_shim`, .envrc files do not get the shimmed command's stdin.
+fn compute_envvars(
+    fastenv_home: &Path,
+    ctx: &core::EnvrcContext,
+    from_shim: bool,
+) -> Result<(), Error> {
     std::fs::create_dir_all(&ctx.env_cache_dir).with_context(|| {
         format!(
             "failed to create cache directory at {}",
             &ctx.env_cache_dir.display()
         )
     })?;
-    let mut temp_script = tempfile::NamedTempFile::new_in(&ctx.root)
-        .with_context(|| format!("failed to create temporary file at {}", ctx.root.display()))?;
-    let temp_script_path = temp_script.path().to_owned();
 
-    let write_failure = || {
+    let layers = ctx.layers();
+    let mut parent: Option<core::EnvCache> = None;
+
+    for (i, layer) in layers.iter().enumerate() {
+        let envrc_contents = std::fs::read(&layer.envrc_path)
+            .with_context(|| format!("failed to read {}", layer.envrc_path.display()))?;
+        check_trust(fastenv_home, &layer.envrc_path, &envrc_contents)?;
+
+        let parent_layer = i.checked_sub(1).map(|i| &layers[i]);
+        let is_nearest = i == layers.len() - 1;
+
+        // Parent layers are only evaluated again if their cache is stale. The nearest .envrc is
+        // always evaluated, as that is what 'fastenv reload' is asked to do.
+        let cached = match core::read_layer_cache(layer)? {
+            Some(cache) if !is_nearest => match cache.meta {
+                Some(ref meta) if meta.stale_reasons(layer, parent_layer)?.is_empty() => {
+                    Some(cache)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let mut cache = match cached {
+            Some(cache) => {
+                log::debug!("reusing cache of {}", layer.envrc_path.display());
+                cache
+            }
+            None => {
+                let parent_hash = match parent_layer {
+                    Some(parent_layer) => core::FileStamp::from_path(&parent_layer.env_cache_path)?
+                        .map(|stamp| stamp.hash),
+                    None => None,
+                };
+                compute_layer(
+                    layer,
+                    &envrc_contents,
+                    parent.as_ref(),
+                    parent_hash,
+                    from_shim,
+                )?
+            }
+        };
+
+        cache.parent = parent.map(Box::new);
+        parent = Some(cache);
+    }
+
+    Ok(())
+}
+
+/// The environment before and after evaluating a layer, and the files it watches.
+type LayerResult = (core::Env, core::Env, Vec<PathBuf>);
+
+/// The environment a layer is evaluated in: that of fastenv itself, with the parent layer applied.
+fn get_base_env(parent: Option<&core::EnvCache>) -> core::Env {
+    let mut env: core::Env = std::env::vars_os().collect();
+    if let Some(parent) = parent {
+        for key in parent.unset_vars() {
+            env.remove(&key);
+        }
+        env.extend(parent.resolve_current());
+    }
+    env
+}
+
+fn run_envrc(
+    layer: &core::EnvrcLayer,
+    envrc_contents: &[u8],
+    parent: Option<&core::EnvCache>,
+    shell: &Shell,
+    prelude: &str,
+    from_shim: bool,
+) -> Result<LayerResult, Error> {
+    let mut temp_script = tempfile::NamedTempFile::new_in(&layer.root).with_context(|| {
         format!(
-            "failed to write to temporary file at {}",
-            temp_script_path.display()
+            "failed to create temporary file at {}",
+            layer.root.display()
         )
-    };
-
-    let prelude = std::env::var("QUICKENV_PRELUDE")
-        .unwrap_or_else(|_| r#"eval "$(direnv stdlib)""#.to_owned());
-
-    write!(
-        temp_script,
-        r##"
-echo '// BEGIN QUICKENV-BEFORE'
-env
-echo '// END QUICKENV-BEFORE'
-{prelude}
-"##,
-    )
-    .with_context(write_failure)?;
+    })?;
+    let temp_script_path = temp_script.path().to_owned();
 
-    io::copy(&mut ctx.envrc, &mut temp_script).with_context(write_failure)?;
+    // The environment is captured in files rather than printed, so that it can never be confused
+    // with what .envrc itself prints.
+    let capture_dir = tempfile::tempdir().context("failed to create temporary directory")?;
+    let quote = |path: &Path| {
+        String::from_utf8_lossy(&export::quote_posix(path.as_os_str().as_bytes())).into_owned()
+    };
 
-    write!(
-        temp_script,
-        r##"
-echo '// BEGIN QUICKENV-AFTER'
-env
-echo '// END QUICKENV-AFTER'
-"##
-    )
-    .with_context(write_failure)?;
+    let fastenv_bin = std::env::current_exe().context("failed to determine path of fastenv")?;
+    temp_script
+        .write_all(&shell.wrap_envrc(
+            envrc_contents,
+            prelude,
+            &quote(&fastenv_bin),
+            &quote(capture_dir.path()),
+        ))
+        .with_context(|| {
+            format!(
+                "failed to write to temporary file at {}",
+                temp_script_path.display()
+            )
+        })?;
 
     signals::pass_control_to_shim();
 
-    let mut cmd = process::Command::new("bash")
-        .arg(&temp_script_path)
-        .env("QUICKENV_NO_SHIM", "1")
-        .stdin(Stdio::inherit())
+    let mut cmd = process::Command::new(&shell.program);
+    cmd.arg(&temp_script_path)
+        .env("FASTENV_NO_SHIM", "1")
+        // input meant for a shimmed command, as in 'cat data | python', is not .envrc's to read
+        .stdin(if from_shim {
+            Stdio::null()
+        } else {
+            Stdio::inherit()
+        })
         .stdout(Stdio::piped())
-        .current_dir(ctx.root)
-        .spawn()
-        .context("failed to spawn bash for running envrc")?;
-
-    let stdout_buf = BufReader::new(cmd.stdout.take().unwrap());
-    let (old_env, new_env) = parse_env_diff(stdout_buf, |line| {
-        io::stdout().write_all(line)?;
-        io::stdout().write_all(b"\n")?;
-        Ok(())
-    })
-    .context("failed to parse envrc output")?;
+        .current_dir(&layer.root);
+
+    if let Some(parent) = parent {
+        cmd.envs(parent.resolve_current());
+        for key in parent.unset_vars() {
+            cmd.env_remove(key);
+        }
+    }
+
+    let mut cmd = cmd.spawn().with_context(|| {
+        format!(
+            "failed to spawn {} for running envrc",
+            shell.program.display()
+        )
+    })?;
+
+    let log_path =
+        Some(core::get_log_path(&layer.env_cache_path)).filter(|_| settings::get().envrc_log);
+    forward_envrc_output(cmd.stdout.take().unwrap(), log_path.as_deref())
+        .context("failed to forward envrc output")?;
 
     let status = cmd.wait().context("failed to wait for envrc subprocess")?;
 
@@ -327,26 +570,261 @@ echo '// END QUICKENV-AFTER'
         Err(anyhow::anyhow!(".envrc exited with status {status}"))?;
     }
 
-    let mut env_cache =
-        BufWriter::new(std::fs::File::create(&ctx.env_cache_path).with_context(|| {
-            format!(
-                "failed to create envrc cache at {}",
-                &ctx.env_cache_path.display()
-            )
-        })?);
+    read_env_capture(capture_dir.path())
+}
 
-    for (key, value) in new_env {
-        if old_env.get(&key) != Some(&value) {
-            env_cache.write_all(key.as_bytes())?;
-            env_cache.write_all(b"=")?;
-            env_cache.write_all(value.as_bytes())?;
-            env_cache.write_all(b"\n")?;
+/// Show what an .envrc prints on stderr, so that it never ends up in the output of 'fastenv reload'
+/// or of a shimmed command. With the `envrc_prefix` setting each line is prefixed with `[envrc]`,
+/// and with `log_path` the output is also written to that file, unprefixed.
+fn forward_envrc_output(output: impl io::Read, log_path: Option<&Path>) -> Result<(), Error> {
+    let mut log_file = match log_path {
+        Some(path) => Some(BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        )),
+        None => None,
+    };
+    let prefix = settings::get().envrc_prefix;
+
+    let mut output = io::BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if output.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if let Some(ref mut log_file) = log_file {
+            log_file.write_all(&line)?;
+        }
+
+        let mut stderr = io::stderr().lock();
+        if prefix {
+            write!(stderr, "[{}] ", style("envrc").dim())?;
+            stderr.write_all(&line)?;
+            if !line.ends_with(b"\n") {
+                stderr.write_all(b"\n")?;
+            }
+        } else {
+            stderr.write_all(&line)?;
         }
     }
 
+    if let Some(mut log_file) = log_file {
+        log_file.flush()?;
+    }
     Ok(())
 }
 
+fn load_toml(
+    layer: &core::EnvrcLayer,
+    contents: &[u8],
+    parent: Option<&core::EnvCache>,
+) -> Result<LayerResult, Error> {
+    let contents = std::str::from_utf8(contents)
+        .with_context(|| format!("{} is not valid UTF-8", layer.envrc_path.display()))?;
+    let env_file = toml_env::parse(contents)
+        .with_context(|| format!("failed to parse {}", layer.envrc_path.display()))?;
+
+    let old_env = get_base_env(parent);
+    let mut new_env = old_env.clone();
+    let watches = env_file
+        .apply(&layer.root, &mut new_env)
+        .with_context(|| format!("failed to load {}", layer.envrc_path.display()))?;
+    Ok((old_env, new_env, watches))
+}
+
+fn load_dotenv(
+    layer: &core::EnvrcLayer,
+    contents: &[u8],
+    parent: Option<&core::EnvCache>,
+) -> Result<LayerResult, Error> {
+    let contents = std::str::from_utf8(contents)
+        .with_context(|| format!("{} is not valid UTF-8", layer.envrc_path.display()))?;
+
+    let old_env = get_base_env(parent);
+    let vars = dotenv::parse(contents, |name| {
+        old_env
+            .get(OsStr::new(name))
+            .map(|value| value.to_string_lossy().into_owned())
+    })
+    .with_context(|| format!("failed to parse {}", layer.envrc_path.display()))?;
+
+    let mut new_env = old_env.clone();
+    new_env.extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+    Ok((old_env, new_env, Vec::new()))
+}
+
+fn load_tool_versions(
+    layer: &core::EnvrcLayer,
+    contents: &[u8],
+    parent: Option<&core::EnvCache>,
+) -> Result<LayerResult, Error> {
+    let contents = std::str::from_utf8(contents)
+        .with_context(|| format!("{} is not valid UTF-8", layer.envrc_path.display()))?;
+    let tools_dir = tool_versions::get_tools_dir().ok_or_else(|| {
+        anyhow::anyhow!("failed to find FASTENV_TOOLS_DIR, ASDF_DATA_DIR or HOME")
+    })?;
+
+    let mut bin_dirs = Vec::new();
+    let mut watches = Vec::new();
+    for tool in tool_versions::parse(contents) {
+        let bin_dir = match tool.bin_dir(&tools_dir, &layer.root) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // watched so that installing a missing tool invalidates the cache
+        watches.push(bin_dir.clone());
+        if !bin_dir.is_dir() {
+            log::warn!(
+                "{} {} is not installed, expected it at {}",
+                tool.tool,
+                tool.version,
+                style(bin_dir.display()).cyan()
+            );
+            continue;
+        }
+
+        bin_dirs.push(bin_dir);
+    }
+
+    let old_env = get_base_env(parent);
+    let mut new_env = old_env.clone();
+    if !bin_dirs.is_empty() {
+        if let Some(path) = old_env.get(OsStr::new("PATH")) {
+            bin_dirs.extend(std::env::split_paths(path));
+        }
+        new_env.insert(
+            "PATH".into(),
+            std::env::join_paths(bin_dirs).context("invalid entry in PATH")?,
+        );
+    }
+
+    Ok((old_env, new_env, watches))
+}
+
+/// Evaluate a single .envrc on top of the environment produced by its parent layer, and write its
+/// cache.
+fn compute_layer(
+    layer: &core::EnvrcLayer,
+    envrc_contents: &[u8],
+    parent: Option<&core::EnvCache>,
+    parent_hash: Option<String>,
+    from_shim: bool,
+) -> Result<core::EnvCache, Error> {
+    let envrc_metadata = std::fs::metadata(&layer.envrc_path)
+        .with_context(|| format!("failed to read {}", layer.envrc_path.display()))?;
+
+    // the shell and prelude only apply to .envrc
+    let mut shell_name = String::new();
+    let mut prelude = String::new();
+
+    let (old_env, new_env, watches) = match layer.source {
+        core::EnvSource::Toml => load_toml(layer, envrc_contents, parent)?,
+        core::EnvSource::Envrc => {
+            let shell = Shell::for_envrc(envrc_contents);
+            shell_name = shell.program.display().to_string();
+            prelude = core::get_prelude(&shell);
+            run_envrc(layer, envrc_contents, parent, &shell, &prelude, from_shim)?
+        }
+        core::EnvSource::Dotenv => load_dotenv(layer, envrc_contents, parent)?,
+        core::EnvSource::ToolVersions => load_tool_versions(layer, envrc_contents, parent)?,
+    };
+
+    let watches = watches
+        .into_iter()
+        .map(|path| {
+            let stamp = core::FileStamp::from_path(&path)
+                .with_context(|| format!("failed to read watched file {}", path.display()))?;
+            Ok(core::WatchedFile { path, stamp })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let unset = old_env
+        .keys()
+        .filter(|key| !new_env.contains_key(*key))
+        .cloned()
+        .collect();
+
+    let mut env = BTreeMap::new();
+    let mut paths = BTreeMap::new();
+
+    for (key, value) in new_env {
+        match old_env.get(&key) {
+            Some(old_value) if *old_value == value => (),
+            Some(old_value) if core::is_path_like(&key) => {
+                match core::PathEdit::compute(old_value, &value) {
+                    Some(edit) => {
+                        paths.insert(key, edit);
+                    }
+                    None => {
+                        env.insert(key, value);
+                    }
+                }
+            }
+            _ => {
+                env.insert(key, value);
+            }
+        }
+    }
+
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    let cache = core::EnvCache {
+        meta: Some(core::CacheMeta {
+            envrc_path: layer.envrc_path.clone(),
+            source: layer.source,
+            created,
+            envrc: core::FileStamp::new(&envrc_metadata, envrc_contents),
+            shell: shell_name,
+            prelude,
+            watches,
+            parent: parent_hash,
+        }),
+        env,
+        paths,
+        unset,
+        parent: None,
+    };
+
+    // The cache is written to a temporary file and moved into place, so that shims running
+    // concurrently never see a partially written cache.
+    let env_cache_dir = layer.env_cache_path.parent().unwrap();
+    let mut env_cache = BufWriter::new(
+        tempfile::NamedTempFile::new_in(env_cache_dir).with_context(|| {
+            format!(
+                "failed to create temporary file at {}",
+                env_cache_dir.display()
+            )
+        })?,
+    );
+
+    core::write_env_cache(&mut env_cache, &cache)
+        .and_then(|()| env_cache.flush())
+        .with_context(|| {
+            format!(
+                "failed to write envrc cache at {}",
+                &layer.env_cache_path.display()
+            )
+        })?;
+
+    env_cache
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .persist(&layer.env_cache_path)
+        .with_context(|| {
+            format!(
+                "failed to write envrc cache at {}",
+                &layer.env_cache_path.display()
+            )
+        })?;
+
+    Ok(cache)
+}
+
 fn get_missing_shims(
     fastenv_home: &Path,
     new_path_envvar: Option<&OsStr>,
@@ -417,12 +895,102 @@ fn command_reload() -> Result<(), Error> {
     let fastenv_home = crate::core::get_fastenv_home()?;
     let mut unshimmed_commands = CheckUnshimmedCommands::new(&fastenv_home)?;
     unshimmed_commands.exclude_current()?;
-    compute_envvars(&fastenv_home)?;
+
+    // Concurrent reloads sharing any layer wait for each other instead of evaluating it in
+    // parallel.
+    let ctx = resolve_envrc_context(&fastenv_home)?;
+    let _lock = core::lock_env_cache(&ctx)?;
+    compute_envvars(&fastenv_home, &ctx, false)?;
     unshimmed_commands.check_unshimmed_commands(false)?;
 
     Ok(())
 }
 
+/// Exit with an error unless the user has approved these contents of the .envrc.
+fn check_trust(fastenv_home: &Path, envrc_path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let hint = match trust::check(fastenv_home, envrc_path, contents)? {
+        trust::TrustStatus::Allowed => return Ok(()),
+        trust::TrustStatus::Unknown => "is not allowed",
+        trust::TrustStatus::Modified => "has changed since it was allowed",
+        trust::TrustStatus::Denied => "is denied",
+    };
+
+    log::error!(
+        "{} {}. Review its contents and run {} to approve it.",
+        style(envrc_path.display()).cyan(),
+        hint,
+        style("'fastenv allow'").magenta()
+    );
+    std::process::exit(1);
+}
+
+/// Resolve the .envrc that 'fastenv allow', 'deny' and 'revoke' operate on.
+fn resolve_envrc_path(fastenv_home: &Path, path: Option<PathBuf>) -> Result<PathBuf, Error> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(resolve_envrc_context(fastenv_home)?.envrc_path),
+    };
+
+    let path = std::fs::canonicalize(&path)
+        .with_context(|| format!("failed to find {}", path.display()))?;
+    if path.is_dir() {
+        let sources = &settings::get().sources;
+        return match core::find_source(&path, sources) {
+            Some((path, _)) => Ok(path),
+            None => anyhow::bail!(
+                "failed to find {} in {}",
+                core::describe_sources(sources),
+                path.display()
+            ),
+        };
+    }
+
+    if !path.is_file() {
+        anyhow::bail!("{} does not exist", path.display());
+    }
+
+    Ok(path)
+}
+
+fn command_allow(path: Option<PathBuf>) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let envrc_path = resolve_envrc_path(&fastenv_home, path)?;
+    let contents = std::fs::read(&envrc_path)
+        .with_context(|| format!("failed to read {}", envrc_path.display()))?;
+
+    trust::allow(&fastenv_home, &envrc_path, &contents)?;
+    log::info!(
+        "Allowed {}. Run {} to load it.",
+        style(envrc_path.display()).cyan(),
+        style("'fastenv reload'").magenta()
+    );
+    Ok(())
+}
+
+fn command_deny(path: Option<PathBuf>) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let envrc_path = resolve_envrc_path(&fastenv_home, path)?;
+
+    trust::deny(&fastenv_home, &envrc_path)?;
+    log::info!("Denied {}.", style(envrc_path.display()).cyan());
+    Ok(())
+}
+
+fn command_revoke(path: Option<PathBuf>) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let envrc_path = resolve_envrc_path(&fastenv_home, path)?;
+
+    if trust::revoke(&fastenv_home, &envrc_path)? {
+        log::info!("Revoked {}.", style(envrc_path.display()).cyan());
+    } else {
+        log::info!(
+            "{} was neither allowed nor denied.",
+            style(envrc_path.display()).cyan()
+        );
+    }
+    Ok(())
+}
+
 enum CheckUnshimmedCommands<'a> {
     Enabled {
         ctx: core::EnvrcContext,
@@ -434,7 +1002,7 @@ enum CheckUnshimmedCommands<'a> {
 
 impl<'a> CheckUnshimmedCommands<'a> {
     fn new(fastenv_home: &'a Path) -> Result<Self, Error> {
-        if std::env::var("QUICKENV_NO_SHIM_WARNINGS").unwrap_or_default() == "1" {
+        if settings::get().no_shim_warnings {
             Ok(CheckUnshimmedCommands::Disabled)
         } else {
             Ok(CheckUnshimmedCommands::Enabled {
@@ -500,7 +1068,7 @@ impl<'a> CheckUnshimmedCommands<'a> {
 
                     log::warn!(
                         "{} unshimmed commands{}. Use {} to make them available.\n\
-                        Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.",
+                        Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.",
                         style(total_missing_shims).green(),
                         new_shims_txt,
                         style("'fastenv shim'").magenta(),
@@ -514,43 +1082,461 @@ impl<'a> CheckUnshimmedCommands<'a> {
     }
 }
 
-fn command_vars() -> Result<(), Error> {
-    let fastenv_home = crate::core::get_fastenv_home()?;
-    let ctx = resolve_envrc_context(&fastenv_home)?;
+fn get_installed_shims(bin_dir: &Path) -> Result<BTreeSet<String>, Error> {
+    let mut rv = BTreeSet::new();
+    let entries = match std::fs::read_dir(bin_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(rv),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read {}", bin_dir.display()));
+        }
+    };
 
-    if let Some(envvars) = core::get_envvars(&ctx)? {
-        for (k, v) in envvars {
-            io::stdout().write_all(k.as_bytes())?;
-            io::stdout().write_all(b"=")?;
-            io::stdout().write_all(v.as_bytes())?;
-            io::stdout().write_all(b"\n")?;
+    for entry in entries {
+        if let Some(name) = entry?.file_name().to_str() {
+            rv.insert(name.to_owned());
         }
+    }
 
-        Ok(())
-    } else {
-        log::error!(
-            "Run {} first to generate envvars",
-            style("'fastenv reload'").magenta()
-        );
-        std::process::exit(1);
+    Ok(rv)
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "less than a minute".to_owned(),
+        60..=3599 => format!("{} minutes", secs / 60),
+        3600..=86399 => format!("{} hours", secs / 3600),
+        _ => format!("{} days", secs / 86400),
     }
 }
 
-fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
+/// Parse a duration such as '30d', '12h', '15m' or '90s' into seconds.
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration {value:?}"))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => {
+            return Err(format!(
+                "invalid duration {value:?} (expected e.g. 30d, 12h or 15m)"
+            ))
+        }
+    };
+    Ok(amount * multiplier)
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("90s"), Ok(90));
+    assert_eq!(parse_duration("15m"), Ok(900));
+    assert_eq!(parse_duration("12h"), Ok(43200));
+    assert_eq!(parse_duration("30d"), Ok(2592000));
+    assert_eq!(parse_duration("2w"), Ok(1209600));
+    assert!(parse_duration("30").is_err());
+    assert!(parse_duration("d").is_err());
+    assert!(parse_duration("1y").is_err());
+}
+
+fn command_gc(dry_run: bool, older_than: Option<u64>) -> Result<(), Error> {
     let fastenv_home = crate::core::get_fastenv_home()?;
-    let bin_dir = fastenv_home.join("bin/");
+    let env_cache_dir = fastenv_home.join("envs");
 
-    let auto = commands.is_empty();
+    let mut entries = match std::fs::read_dir(&env_cache_dir) {
+        Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read {}", env_cache_dir.display()));
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
 
-    if auto {
-        let ctx = resolve_envrc_context(&fastenv_home)?;
-        let envvars = match crate::core::get_envvars(&ctx)? {
-            Some(x) => x,
-            None => {
-                log::error!(
-                    "Run {} first to generate envvars",
-                    style("'fastenv reload'").magenta()
-                );
+    let now = std::time::SystemTime::now();
+    let mut removed = 0;
+
+    for entry in entries {
+        let path = entry.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // lock and log files go along with their cache, temporary files belong to a reload in
+        // progress
+        if name.ends_with(".lock") || name.ends_with(".log") || name.starts_with('.') {
+            continue;
+        }
+
+        let reason = match std::fs::read(&path)
+            .map_err(core::Error::from)
+            .and_then(|contents| core::parse_env_cache(&contents, &path))
+        {
+            Err(core::Error::Io(e)) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()))
+            }
+            Err(_) => Some("corrupt".to_owned()),
+            Ok(core::EnvCache {
+                meta: Some(meta), ..
+            }) if !meta.envrc_path.exists() => {
+                Some(format!("{} no longer exists", meta.envrc_path.display()))
+            }
+            Ok(_) => {
+                // shims bump the modification time when they use a cache
+                let unused = now
+                    .duration_since(entry.metadata()?.modified()?)
+                    .map_or(0, |duration| duration.as_secs());
+                match older_than {
+                    Some(older_than) if unused > older_than => {
+                        Some(format!("not used in {}", format_age(unused)))
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        let reason = match reason {
+            Some(reason) => reason,
+            None => continue,
+        };
+
+        if dry_run {
+            log::info!("Would remove {} ({})", style(path.display()).cyan(), reason);
+        } else {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+            for (extra_path, what) in [
+                (core::get_lock_path(&path), "lock file"),
+                (core::get_log_path(&path), "log file"),
+            ] {
+                match std::fs::remove_file(extra_path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                        return Err(e).with_context(|| format!("failed to remove {what}"));
+                    }
+                    _ => (),
+                }
+            }
+            log::info!("Removed {} ({})", style(path.display()).cyan(), reason);
+        }
+        removed += 1;
+    }
+
+    if dry_run {
+        log::info!("Would remove {} env caches.", style(removed).green());
+    } else {
+        log::info!("Removed {} env caches.", style(removed).green());
+    }
+
+    Ok(())
+}
+
+fn command_status(json: bool) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let bin_dir = fastenv_home.join("bin/");
+    let shims = get_installed_shims(&bin_dir)?;
+
+    let ctx = match resolve_envrc_context(&fastenv_home) {
+        Ok(ctx) => Some(ctx),
+        Err(core::Error::NoEnvrc(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let cache = match ctx {
+        Some(ref ctx) => core::read_env_cache(ctx)?,
+        None => None,
+    };
+
+    let trust = match ctx {
+        Some(ref ctx) => {
+            let contents = std::fs::read(&ctx.envrc_path)
+                .with_context(|| format!("failed to read {}", ctx.envrc_path.display()))?;
+            Some(trust::check(&fastenv_home, &ctx.envrc_path, &contents)?)
+        }
+        None => None,
+    };
+
+    let stale_reasons = match (&ctx, &cache) {
+        (Some(ctx), Some(cache)) => cache.stale_reasons(ctx)?,
+        _ => Vec::new(),
+    };
+
+    let unshimmed_commands = match cache {
+        Some(ref cache) => {
+            let envvars = cache.resolve_current();
+            let path_envvar = envvars.get(OsStr::new("PATH")).map(OsString::as_os_str);
+            get_missing_shims(&fastenv_home, path_envvar)?
+        }
+        None => BTreeSet::new(),
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    // caches from older versions of fastenv do not record their creation time
+    let created = match (&ctx, &cache) {
+        (
+            _,
+            Some(core::EnvCache {
+                meta: Some(meta), ..
+            }),
+        ) => Some(meta.created),
+        (Some(ctx), Some(_)) => std::fs::metadata(&ctx.env_cache_path)?
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_secs()),
+        _ => None,
+    };
+    let age = created.map(|created| now.saturating_sub(created));
+
+    if json {
+        let cache_json = match (&ctx, &cache) {
+            (Some(ctx), Some(cache)) => serde_json::json!({
+                "path": ctx.env_cache_path.to_string_lossy(),
+                "created": created,
+                "age_secs": age,
+                "stale": !stale_reasons.is_empty(),
+                "stale_reasons": stale_reasons,
+                "variables_set": cache.resolve_current().len(),
+                "variables_unset": cache.unset_vars().len(),
+            }),
+            _ => serde_json::Value::Null,
+        };
+
+        let status = serde_json::json!({
+            "envrc": ctx.as_ref().map(|ctx| ctx.envrc_path.to_string_lossy()),
+            "source": ctx.as_ref().map(|ctx| ctx.source.as_str()),
+            "root": ctx.as_ref().map(|ctx| ctx.root.to_string_lossy()),
+            "parents": ctx.as_ref().map_or_else(Vec::new, |ctx| ctx
+                .parents
+                .iter()
+                .map(|layer| layer.envrc_path.to_string_lossy())
+                .collect()),
+            "trust": trust.map(trust::TrustStatus::as_str),
+            "cache": cache_json,
+            "shims": shims,
+            "unshimmed_commands": unshimmed_commands,
+        });
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    match ctx {
+        Some(ref ctx) => {
+            println!(".envrc: {}", style(ctx.envrc_path.display()).cyan());
+            if !ctx.parents.is_empty() {
+                println!("layered on:");
+                for layer in &ctx.parents {
+                    println!("  {}", style(layer.envrc_path.display()).cyan());
+                }
+            }
+        }
+        None => println!(
+            ".envrc: {}",
+            style("none found in current or any parent directory").red()
+        ),
+    }
+
+    match trust {
+        Some(trust::TrustStatus::Allowed) => println!("trust: {}", style("allowed").green()),
+        Some(trust) => println!(
+            "trust: {}, run {} to approve it",
+            style(trust.as_str()).red(),
+            style("'fastenv allow'").magenta()
+        ),
+        None => (),
+    }
+
+    match (&ctx, &cache) {
+        (Some(ctx), Some(cache)) => {
+            println!("cache: {}", style(ctx.env_cache_path.display()).cyan());
+            if let Some(age) = age {
+                println!("cache age: {}", format_age(age));
+            }
+            if stale_reasons.is_empty() {
+                println!("cache status: {}", style("up to date").green());
+            } else {
+                println!("cache status: {}", style("stale").red());
+                for reason in &stale_reasons {
+                    println!("  {reason}");
+                }
+            }
+            println!(
+                "variables: {} set, {} unset",
+                style(cache.resolve_current().len()).green(),
+                style(cache.unset_vars().len()).green()
+            );
+        }
+        (Some(ctx), None) => {
+            println!("cache: {}", style(ctx.env_cache_path.display()).cyan());
+            println!(
+                "cache status: {}, run {} to create it",
+                style("missing").red(),
+                style("'fastenv reload'").magenta()
+            );
+        }
+        (None, _) => (),
+    }
+
+    print_command_list("shims", &shims);
+    print_command_list("unshimmed commands", &unshimmed_commands);
+
+    Ok(())
+}
+
+fn print_command_list(label: &str, commands: &BTreeSet<String>) {
+    if commands.is_empty() {
+        println!("{label}: none");
+    } else {
+        println!(
+            "{label} ({}): {}",
+            style(commands.len()).green(),
+            commands.iter().cloned().collect::<Vec<_>>().join(" ")
+        );
+    }
+}
+
+fn command_vars(format: export::VarsFormat) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let ctx = resolve_envrc_context(&fastenv_home)?;
+
+    if let Some(cache) = core::read_env_cache(&ctx)? {
+        mark_env_cache_used(&ctx);
+
+        let mut stdout = io::stdout().lock();
+        export::write_vars(
+            &mut stdout,
+            format,
+            &cache.resolve_current(),
+            &cache.unset_vars(),
+        )?;
+        stdout.flush()?;
+
+        Ok(())
+    } else {
+        log::error!(
+            "Run {} first to generate envvars",
+            style("'fastenv reload'").magenta()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn command_diff(color: ColorChoice) -> Result<(), Error> {
+    match color {
+        ColorChoice::Auto => (),
+        ColorChoice::Always => console::set_colors_enabled(true),
+        ColorChoice::Never => console::set_colors_enabled(false),
+    }
+
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let ctx = resolve_envrc_context(&fastenv_home)?;
+
+    let cache = match core::read_env_cache(&ctx)? {
+        Some(x) => x,
+        None => {
+            log::error!(
+                "Run {} first to generate envvars",
+                style("'fastenv reload'").magenta()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let mut changes = 0;
+
+    for (key, new_value) in cache.resolve_current() {
+        let key_display = key.to_string_lossy();
+        match std::env::var_os(&key) {
+            None => {
+                changes += 1;
+                println!(
+                    "{} {}={}",
+                    style("+").green(),
+                    style(key_display).green(),
+                    new_value.to_string_lossy()
+                );
+            }
+            Some(old_value) if old_value == new_value => (),
+            Some(old_value) if core::is_path_like(&key) => {
+                changes += 1;
+                println!("{} {}", style("~").yellow(), style(key_display).yellow());
+                let old_entries = core::split_path_var(&old_value);
+                let new_entries = core::split_path_var(&new_value);
+                for entry in &new_entries {
+                    if !old_entries.contains(entry) {
+                        println!("    {} {}", style("+").green(), entry.to_string_lossy());
+                    }
+                }
+                for entry in &old_entries {
+                    if !new_entries.contains(entry) {
+                        println!("    {} {}", style("-").red(), entry.to_string_lossy());
+                    }
+                }
+            }
+            Some(old_value) => {
+                changes += 1;
+                println!("{} {}", style("~").yellow(), style(key_display).yellow());
+                println!("    {} {}", style("-").red(), old_value.to_string_lossy());
+                println!("    {} {}", style("+").green(), new_value.to_string_lossy());
+            }
+        }
+    }
+
+    for key in &cache.unset_vars() {
+        if let Some(old_value) = std::env::var_os(key) {
+            changes += 1;
+            println!(
+                "{} {}={}",
+                style("-").red(),
+                style(key.to_string_lossy()).red(),
+                old_value.to_string_lossy()
+            );
+        }
+    }
+
+    if changes == 0 {
+        log::info!(
+            "The current environment already matches {}.",
+            style(".envrc").cyan()
+        );
+    }
+
+    Ok(())
+}
+
+fn command_shim(mut commands: Vec<String>, yes: bool, only_in: Vec<PathBuf>) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let bin_dir = fastenv_home.join("bin/");
+
+    let only_in = only_in
+        .into_iter()
+        .map(|dir| {
+            std::fs::canonicalize(&dir)
+                .with_context(|| format!("failed to find directory {}", dir.display()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let auto = commands.is_empty();
+
+    let root = match resolve_envrc_context(&fastenv_home) {
+        Ok(ctx) => Some(ctx.root),
+        Err(core::Error::NoEnvrc(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    if auto {
+        let ctx = resolve_envrc_context(&fastenv_home)?;
+        let envvars = match crate::core::get_envvars(&ctx)? {
+            Some(x) => x,
+            None => {
+                log::error!(
+                    "Run {} first to generate envvars",
+                    style("'fastenv reload'").magenta()
+                );
                 std::process::exit(1);
             }
         };
@@ -569,12 +1555,12 @@ fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
             eprintln!();
             if commands.len() == 1 {
                 eprintln!(
-                    "Quickenv will create this new shim binary in {}.",
+                    "fastenv will create this new shim binary in {}.",
                     style(bin_dir.display()).cyan()
                 );
             } else {
                 eprintln!(
-                    "Quickenv will create these {} new shim binaries in {}.",
+                    "fastenv will create these {} new shim binaries in {}.",
                     style(commands.len()).green(),
                     style(bin_dir.display()).cyan()
                 );
@@ -606,6 +1592,10 @@ fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
     let self_binary = which::which("fastenv")?;
 
     let mut changes = 0;
+    let mut registry = shims::Registry::load(&fastenv_home)?;
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
 
     for command in &commands {
         if command == "fastenv" {
@@ -616,6 +1606,24 @@ fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
         let command_path = bin_dir.join(command);
 
         let was_there = std::fs::remove_file(&command_path).is_ok();
+        if !was_there {
+            // left over from a shim that was removed by hand
+            registry.remove(command);
+        }
+        registry.add(
+            command,
+            shims::ShimRecord {
+                root: root.clone(),
+                created,
+                origin: if auto {
+                    shims::ShimOrigin::Auto
+                } else {
+                    shims::ShimOrigin::Explicit
+                },
+                only_in: only_in.clone(),
+            },
+        );
+
         symlink(&self_binary, &command_path).with_context(|| {
             format!(
                 "failed to symlink {} to {}",
@@ -646,6 +1654,8 @@ fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
         }
     }
 
+    registry.save(&fastenv_home)?;
+
     if changes == 0 {
         log::info!("created {} new shims.", style("no").red());
     } else {
@@ -671,22 +1681,34 @@ fn command_shim(mut commands: Vec<String>, yes: bool) -> Result<(), Error> {
     Ok(())
 }
 
-fn command_unshim(commands: Vec<String>) -> Result<(), Error> {
+fn command_unshim(mut commands: Vec<String>, project: Option<PathBuf>) -> Result<(), Error> {
     let fastenv_dir = crate::core::get_fastenv_home()?;
     let bin_dir = fastenv_dir.join("bin/");
     let mut changes = 0;
+    let mut registry = shims::Registry::load(&fastenv_dir)?;
+
+    if let Some(project) = project {
+        let project = std::fs::canonicalize(&project)
+            .with_context(|| format!("failed to find directory {}", project.display()))?;
+        commands.extend(registry.created_in(&project));
+    }
+
     for command in &commands {
         if command == "fastenv" {
             log::warn!("not unshimming own binary");
             continue;
         }
 
+        registry.remove(command);
+
         let command_path = bin_dir.join(command);
         if std::fs::remove_file(&command_path).is_ok() {
             changes += 1;
         }
     }
 
+    registry.save(&fastenv_dir)?;
+
     log::info!(
         "Removed {} shims from {}.\nUse {} to add them again",
         style(changes).green(),
@@ -697,19 +1719,617 @@ fn command_unshim(commands: Vec<String>) -> Result<(), Error> {
     Ok(())
 }
 
-fn exec_shimmed_binary(program_name: &OsStr, args: Vec<OsString>) -> Result<(), Error> {
+/// What 'fastenv list' finds in the bin directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinEntryKind {
+    /// A symlink to fastenv itself.
+    Shim,
+    /// Anything else, such as a binary or a symlink to one.
+    Foreign,
+    /// A symlink whose target no longer exists.
+    Dangling,
+}
+
+impl BinEntryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            BinEntryKind::Shim => "shim",
+            BinEntryKind::Foreign => "foreign",
+            BinEntryKind::Dangling => "dangling",
+        }
+    }
+}
+
+/// Classify an entry of the bin directory, returning the target if it is a symlink.
+fn classify_bin_entry(
+    path: &Path,
+    self_binary: &Path,
+) -> Result<(BinEntryKind, Option<PathBuf>), Error> {
+    let link_target = match std::fs::read_link(path) {
+        Ok(target) => Some(target),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => None,
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let kind = match std::fs::canonicalize(path) {
+        Ok(target) if target == self_binary => BinEntryKind::Shim,
+        Ok(_) => BinEntryKind::Foreign,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => BinEntryKind::Dangling,
+        Err(e) => return Err(e).with_context(|| format!("failed to resolve {}", path.display())),
+    };
+
+    Ok((kind, link_target))
+}
+
+fn command_list(json: bool, long: bool) -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let bin_dir = fastenv_home.join("bin/");
+    let registry = shims::Registry::load(&fastenv_home)?;
+    let self_binary = std::env::current_exe()
+        .and_then(std::fs::canonicalize)
+        .context("failed to determine path of fastenv")?;
+
+    let ctx = match resolve_envrc_context(&fastenv_home) {
+        Ok(ctx) => Some(ctx),
+        Err(core::Error::NoEnvrc(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    // Unlike a shim, listing never reloads the cache or warns about it being stale.
+    let cache = match ctx {
+        Some(ref ctx) => core::read_env_cache(ctx)?,
+        None => None,
+    };
+
+    let mut entries = Vec::new();
+    for name in get_installed_shims(&bin_dir)? {
+        let (kind, link_target) = classify_bin_entry(&bin_dir.join(&name), &self_binary)?;
+        let resolved = match kind {
+            BinEntryKind::Shim => {
+                let cache = cache.as_ref().filter(|_| {
+                    ctx.as_ref()
+                        .is_some_and(|ctx| registry.applies_to(&name, &ctx.root))
+                });
+                // PATH entries added by .envrc may be relative to the project
+                resolve_shimmed_binary(&fastenv_home, &name, cache)
+                    .ok()
+                    .map(|result| std::path::absolute(&result.path).unwrap_or(result.path))
+            }
+            _ => None,
+        };
+        entries.push((name, kind, link_target, resolved));
+    }
+
+    if json {
+        let entries = entries
+            .iter()
+            .map(|(name, kind, link_target, resolved)| {
+                let record = registry.shims.get(name);
+                serde_json::json!({
+                    "name": name,
+                    "kind": kind.as_str(),
+                    "link_target": link_target.as_ref().map(|path| path.to_string_lossy()),
+                    "resolves_to": resolved.as_ref().map(|path| path.to_string_lossy()),
+                    "origin": record.map(|record| record.origin.as_str()),
+                    "project": record
+                        .and_then(|record| record.root.as_ref())
+                        .map(|root| root.to_string_lossy()),
+                    "created": record.map(|record| record.created),
+                    "only_in": record.map_or_else(Vec::new, |record| record
+                        .only_in
+                        .iter()
+                        .map(|dir| dir.to_string_lossy())
+                        .collect()),
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        log::info!(
+            "No shims in {}. Use {} to create some.",
+            style(bin_dir.display()).cyan(),
+            style("'fastenv shim'").magenta()
+        );
+        return Ok(());
+    }
+
+    if long {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        for (name, kind, link_target, resolved) in &entries {
+            let record = registry.shims.get(name);
+            match record {
+                Some(record) => println!(
+                    "{} ({}, {})",
+                    style(name).green(),
+                    kind.as_str(),
+                    record.origin.as_str()
+                ),
+                None => println!("{} ({})", style(name).green(), kind.as_str()),
+            }
+
+            match (kind, link_target, resolved) {
+                (BinEntryKind::Shim, _, Some(resolved)) => {
+                    println!("  resolves to: {}", style(resolved.display()).cyan())
+                }
+                (BinEntryKind::Shim, _, None) => {
+                    println!("  resolves to: {}", style("not found").red())
+                }
+                (_, Some(link_target), _) => {
+                    println!("  links to: {}", style(link_target.display()).cyan())
+                }
+                (_, None, _) => (),
+            }
+
+            if let Some(record) = record {
+                match record.root {
+                    Some(ref root) => println!("  project: {}", style(root.display()).cyan()),
+                    None => println!("  project: none"),
+                }
+                println!(
+                    "  created: {} ago",
+                    format_age(now.saturating_sub(record.created))
+                );
+                if !record.only_in.is_empty() {
+                    let only_in = record
+                        .only_in
+                        .iter()
+                        .map(|dir| style(dir.display()).cyan().to_string())
+                        .collect::<Vec<_>>();
+                    println!("  only in: {}", only_in.join(" "));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    for (kind, label) in [
+        (BinEntryKind::Shim, "shims"),
+        (BinEntryKind::Foreign, "foreign files"),
+        (BinEntryKind::Dangling, "dangling links"),
+    ] {
+        let cells = entries
+            .iter()
+            .filter(|(_, entry_kind, _, _)| *entry_kind == kind)
+            .map(
+                |(name, _, link_target, resolved)| match (kind, link_target, resolved) {
+                    (BinEntryKind::Shim, _, Some(resolved)) => {
+                        format!("{name} -> {}", resolved.display())
+                    }
+                    (BinEntryKind::Shim, _, None) => format!("{name} -> not found"),
+                    (BinEntryKind::Dangling, Some(link_target), _) => {
+                        format!("{name} -> {}", link_target.display())
+                    }
+                    _ => name.clone(),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        if !cells.is_empty() {
+            println!("{label} ({}):", style(cells.len()).green());
+            grid::print_as_grid_stdout(&cells);
+        }
+    }
+
+    Ok(())
+}
+
+fn command_config(command: ConfigCommand) -> Result<(), Error> {
+    let config = settings::Config::load()?;
+
+    match command {
+        ConfigCommand::Get { key } => {
+            let key = settings::find_key(&key)?;
+            match config.lookup(key)? {
+                Some((value, _)) => println!("{}", value),
+                None => std::process::exit(1),
+            }
+        }
+        ConfigCommand::Set {
+            project,
+            key,
+            value,
+        } => {
+            let key = settings::find_key(&key)?;
+            let value = key.to_toml(&value)?;
+            let mut file = config_file_to_edit(config, project)?;
+            if project && key.global_only {
+                return Err(settings::Error::GlobalOnly(key.name, file.path).into());
+            }
+            file.values.insert(key.name.to_owned(), value);
+            file.save()?;
+            log::info!(
+                "Set {} in {}.",
+                style(key.name).green(),
+                style(file.path.display()).cyan()
+            );
+
+            let config = settings::Config::load()?;
+            if let Some((_, origin)) = config.lookup(key)? {
+                let location = origin.location();
+                if location != file.path.display().to_string() {
+                    log::warn!("{} takes precedence over it.", style(location).cyan());
+                }
+            }
+        }
+        ConfigCommand::Unset { project, key } => {
+            // unknown settings can be removed too, as they make all other commands fail
+            let mut file = config_file_to_edit(config, project)?;
+            if file.values.remove(&key).is_some() {
+                file.save()?;
+                log::info!(
+                    "Removed {} from {}.",
+                    style(key).green(),
+                    style(file.path.display()).cyan()
+                );
+            } else {
+                log::info!(
+                    "{} is not set in {}.",
+                    style(key).green(),
+                    style(file.path.display()).cyan()
+                );
+            }
+        }
+        ConfigCommand::List => {
+            for key in settings::KEYS {
+                if let Some((value, origin)) = config.lookup(key)? {
+                    let value = match key.kind {
+                        settings::Kind::Bool => value,
+                        settings::Kind::String => format!("{:?}", value),
+                    };
+                    println!("{} = {}  # {}", key.name, value, origin.location());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The config file 'fastenv config set' and 'fastenv config unset' change.
+fn config_file_to_edit(
+    config: settings::Config,
+    project: bool,
+) -> Result<settings::ConfigFile, Error> {
+    if !project {
+        return Ok(config.global);
+    }
+
+    match config.project {
+        Some(file) => Ok(file),
+        None => Ok(settings::ConfigFile::load(
+            std::env::current_dir()?.join(settings::PROJECT_CONFIG_NAME),
+        )?),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CheckOutcome {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The result of a single check run by 'fastenv doctor'.
+struct Check {
+    outcome: CheckOutcome,
+    message: String,
+    /// How to fix the problem, for checks that did not pass.
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(message: String) -> Self {
+        Check {
+            outcome: CheckOutcome::Pass,
+            message,
+            hint: None,
+        }
+    }
+
+    fn warn(message: String, hint: String) -> Self {
+        Check {
+            outcome: CheckOutcome::Warn,
+            message,
+            hint: Some(hint),
+        }
+    }
+
+    fn fail(message: String, hint: String) -> Self {
+        Check {
+            outcome: CheckOutcome::Fail,
+            message,
+            hint: Some(hint),
+        }
+    }
+}
+
+fn command_doctor() -> Result<(), Error> {
+    let fastenv_home = crate::core::get_fastenv_home()?;
+    let bin_dir = fastenv_home.join("bin");
+
+    let mut checks = vec![
+        check_settings(),
+        check_path(&bin_dir),
+        check_shell(),
+        check_stdlib(),
+    ];
+    checks.extend(check_shims(&bin_dir)?);
+    checks.extend(check_caches(&fastenv_home.join("envs"))?);
+
+    for check in &checks {
+        let label = match check.outcome {
+            CheckOutcome::Pass => style("[pass]").green(),
+            CheckOutcome::Warn => style("[warn]").yellow(),
+            CheckOutcome::Fail => style("[fail]").red(),
+        };
+        println!("{} {}", label, check.message);
+        if let Some(ref hint) = check.hint {
+            println!("       {hint}");
+        }
+    }
+
+    let failures = checks
+        .iter()
+        .filter(|check| check.outcome == CheckOutcome::Fail)
+        .count();
+    if failures > 0 {
+        log::error!("{} checks failed.", style(failures).red());
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn is_own_bin_dir(bin_dir: &Path, entry: &Path) -> bool {
+    bin_dir == entry || std::fs::canonicalize(entry).is_ok_and(|x| x == bin_dir)
+}
+
+fn check_path(bin_dir: &Path) -> Check {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    match std::env::split_paths(&path).position(|entry| is_own_bin_dir(bin_dir, &entry)) {
+        Some(0) => Check::pass(format!("{} is first on PATH", bin_dir.display())),
+        Some(_) => Check::warn(
+            format!("{} is on PATH, but not first", bin_dir.display()),
+            "Executables in earlier PATH entries take precedence over shims. Move it to the \
+            front of PATH."
+                .to_owned(),
+        ),
+        None => Check::fail(
+            format!("{} is not on PATH", bin_dir.display()),
+            format!(
+                "Add {} to your shell's configuration.",
+                style(format!("'export PATH=\"{}:$PATH\"'", bin_dir.display())).magenta()
+            ),
+        ),
+    }
+}
+
+fn check_settings() -> Check {
+    match settings::load() {
+        Ok((_, None)) => Check::pass("settings are valid".to_owned()),
+        Ok((_, Some(e))) | Err(e) => Check::fail(
+            e.to_string(),
+            format!(
+                "Fix it with {}, or by editing the file.",
+                style("'fastenv config set'").magenta()
+            ),
+        ),
+    }
+}
+
+fn check_shell() -> Check {
+    let shell = settings::get().shell.clone().unwrap_or_else(Shell::bash);
+    match which::which(&shell.program) {
+        Ok(path) => Check::pass(format!(
+            ".envrc files without a shebang are evaluated with {}",
+            path.display()
+        )),
+        Err(_) => Check::fail(
+            format!("{} is not installed", shell.program.display()),
+            format!(
+                "Install it, or choose another shell with {}.",
+                style("'fastenv config set shell'").magenta()
+            ),
+        ),
+    }
+}
+
+fn check_stdlib() -> Check {
+    let settings = settings::get();
+    if settings.prelude.is_some() {
+        return Check::pass(".envrc files are evaluated with the configured prelude".to_owned());
+    }
+
+    match (settings.stdlib, which::which("direnv")) {
+        (core::Stdlib::Builtin, _) => {
+            Check::pass(".envrc files are evaluated with the built-in stdlib".to_owned())
+        }
+        (_, Ok(direnv)) => Check::pass(format!(
+            ".envrc files are evaluated with direnv's stdlib from {}",
+            direnv.display()
+        )),
+        (core::Stdlib::Direnv, Err(_)) => Check::fail(
+            "stdlib is set to direnv, but direnv is not installed".to_owned(),
+            "Install direnv, or set stdlib to builtin.".to_owned(),
+        ),
+        (core::Stdlib::Auto, Err(_)) => Check::pass(
+            "direnv is not installed, .envrc files are evaluated with the built-in stdlib"
+                .to_owned(),
+        ),
+    }
+}
+
+/// Check that every entry of the bin directory is a shim pointing at this fastenv binary, and that
+/// no shim is shadowed by another executable on PATH.
+fn check_shims(bin_dir: &Path) -> Result<Vec<Check>, Error> {
+    let self_binary = std::env::current_exe()
+        .and_then(std::fs::canonicalize)
+        .context("failed to determine path of fastenv")?;
+
+    let mut checks = Vec::new();
+    let mut valid = 0;
+
+    for name in get_installed_shims(bin_dir)? {
+        let path = bin_dir.join(&name);
+        let (kind, link_target) = classify_bin_entry(&path, &self_binary)?;
+
+        match (kind, link_target) {
+            (BinEntryKind::Dangling, link_target) => checks.push(Check::fail(
+                format!(
+                    "{} links to {}, which does not exist",
+                    path.display(),
+                    link_target.unwrap_or_default().display()
+                ),
+                format!(
+                    "Run {} to recreate it, or {} to remove it.",
+                    style(format!("'fastenv shim {name}'")).magenta(),
+                    style(format!("'fastenv unshim {name}'")).magenta()
+                ),
+            )),
+            (BinEntryKind::Foreign, Some(link_target))
+                if link_target.file_name() == Some(OsStr::new("fastenv")) =>
+            {
+                checks.push(Check::warn(
+                    format!(
+                        "{} links to another fastenv binary at {}",
+                        path.display(),
+                        link_target.display()
+                    ),
+                    format!(
+                        "Run {} to point it at the current one.",
+                        style(format!("'fastenv shim {name}'")).magenta()
+                    ),
+                ))
+            }
+            (BinEntryKind::Foreign, _) => checks.push(Check::warn(
+                format!("{} is not a fastenv shim", path.display()),
+                format!(
+                    "Move it out of {}, which is managed by fastenv.",
+                    bin_dir.display()
+                ),
+            )),
+            (BinEntryKind::Shim, _) => match which::which(&name) {
+                Ok(effective) if effective != path => checks.push(Check::fail(
+                    format!(
+                        "{} is shadowed by an executable of the same name at {}",
+                        path.display(),
+                        effective.display()
+                    ),
+                    format!(
+                        "Move {} to the front of PATH, or remove the other executable.",
+                        bin_dir.display()
+                    ),
+                )),
+                _ => valid += 1,
+            },
+        }
+    }
+
+    if checks.is_empty() {
+        checks.push(Check::pass(format!(
+            "{} shims in {} are valid",
+            valid,
+            bin_dir.display()
+        )));
+    }
+
+    Ok(checks)
+}
+
+/// Check that every env cache can be read and still belongs to an existing .envrc.
+fn check_caches(env_cache_dir: &Path) -> Result<Vec<Check>, Error> {
+    let mut entries = match std::fs::read_dir(env_cache_dir) {
+        Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read {}", env_cache_dir.display()));
+        }
+    };
+
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut checks = Vec::new();
+    let mut valid = 0;
+
+    for entry in entries {
+        let path = entry.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // lock and log files, and temporary files of a reload in progress
+        if name.ends_with(".lock") || name.ends_with(".log") || name.starts_with('.') {
+            continue;
+        }
+
+        let cache = std::fs::read(&path)
+            .map_err(core::Error::from)
+            .and_then(|contents| core::parse_env_cache(&contents, &path));
+
+        match cache {
+            Err(e) => checks.push(Check::fail(
+                match e {
+                    core::Error::Io(e) => format!("{} cannot be read: {}", path.display(), e),
+                    e => e.to_string(),
+                },
+                format!(
+                    "Delete it and run {} in its project.",
+                    style("'fastenv reload'").magenta()
+                ),
+            )),
+            Ok(core::EnvCache {
+                meta: Some(meta), ..
+            }) if !meta.envrc_path.exists() => checks.push(Check::warn(
+                format!(
+                    "{} belongs to {}, which no longer exists",
+                    path.display(),
+                    meta.envrc_path.display()
+                ),
+                format!("Run {} to remove it.", style("'fastenv gc'").magenta()),
+            )),
+            Ok(_) => valid += 1,
+        }
+    }
+
+    if checks.is_empty() {
+        checks.push(Check::pass(format!(
+            "{} env caches in {} are valid",
+            valid,
+            env_cache_dir.display()
+        )));
+    }
+
+    Ok(checks)
+}
+
+/// Run the actual binary behind a shim. `as_shim` is false for 'fastenv exec', which applies
+/// .envrc regardless of where the shim is restricted to.
+fn exec_shimmed_binary(
+    program_name: &OsStr,
+    args: Vec<OsString>,
+    as_shim: bool,
+) -> Result<(), Error> {
     log::debug!("attempting to launch shim for {:?}", program_name);
 
     let fastenv_home = crate::core::get_fastenv_home()?;
-    let shimmed_binary_result =
-        find_shimmed_binary(&fastenv_home, program_name).context("failed to find actual binary")?;
+    let shimmed_binary_result = find_shimmed_binary(&fastenv_home, program_name, as_shim)
+        .context("failed to find actual binary")?;
 
-    if std::env::var("QUICKENV_SHIM_EXEC").unwrap_or_default() == "1" {
+    if settings::get().shim_exec {
         for (k, v) in shimmed_binary_result.envvars_override {
             log::debug!("export {:?}={:?}", k, v);
             std::env::set_var(k, v);
         }
 
+        for k in shimmed_binary_result.envvars_remove {
+            log::debug!("unset {:?}", k);
+            std::env::remove_var(k);
+        }
+
         log::debug!("execvp {}", shimmed_binary_result.path.display());
 
         let mut full_args = vec![shimmed_binary_result.path.clone().into_os_string()];
@@ -721,11 +2341,13 @@ fn exec_shimmed_binary(program_name: &OsStr, args: Vec<OsString>) -> Result<(),
             CheckUnshimmedCommands::new(&fastenv_home).unwrap_or(CheckUnshimmedCommands::Disabled);
         let _ignored = unshimmed_commands.exclude_current();
 
-        let exitcode = process::Command::new(shimmed_binary_result.path)
-            .args(args)
-            .envs(shimmed_binary_result.envvars_override)
-            .status()
-            .context("failed to spawn shim subcommand")?;
+        let mut cmd = process::Command::new(shimmed_binary_result.path);
+        cmd.args(args).envs(shimmed_binary_result.envvars_override);
+        for k in shimmed_binary_result.envvars_remove {
+            cmd.env_remove(k);
+        }
+
+        let exitcode = cmd.status().context("failed to spawn shim subcommand")?;
 
         let _ignored = unshimmed_commands.check_unshimmed_commands(true);
 
@@ -741,27 +2363,60 @@ fn exec_shimmed_binary(program_name: &OsStr, args: Vec<OsString>) -> Result<(),
 struct ShimmedBinaryResult {
     path: PathBuf,
     envvars_override: core::Env,
+    envvars_remove: BTreeSet<OsString>,
 }
 
 fn find_shimmed_binary(
     fastenv_home: &Path,
     program_name: &OsStr,
+    as_shim: bool,
 ) -> Result<ShimmedBinaryResult, Error> {
-    let mut envvars_override = BTreeMap::<OsString, OsString>::new();
+    let program_basename = Path::new(&program_name)
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap();
 
-    if std::env::var("QUICKENV_NO_SHIM").unwrap_or_default() != "1" {
-        match resolve_envrc_context(fastenv_home).and_then(|ctx| core::get_envvars(&ctx)) {
-            Ok(None) => (),
-            Ok(Some(envvars)) => {
-                envvars_override.extend(envvars);
+    let mut cache = None;
+    if !settings::get().no_shim {
+        match resolve_envrc_context(fastenv_home) {
+            Ok(ctx)
+                if as_shim
+                    && !shims::Registry::load(fastenv_home)?
+                        .applies_to(program_basename, &ctx.root) =>
+            {
+                log::debug!(
+                    "{} is not shimmed in {}, not applying .envrc",
+                    program_basename,
+                    ctx.root.display()
+                );
             }
-            Err(core::Error::NoEnvrc) => (),
+            Ok(ctx) => cache = read_cache_for_shim(fastenv_home, &ctx)?,
+            Err(core::Error::NoEnvrc(_)) => (),
             Err(e) => {
                 return Err(e).context("failed to get environment variables from .envrc");
             }
         }
     }
 
+    resolve_shimmed_binary(fastenv_home, program_basename, cache.as_ref())
+}
+
+/// Find the binary behind a shim on PATH, with the given env cache applied and fastenv's own bin
+/// directory removed.
+fn resolve_shimmed_binary(
+    fastenv_home: &Path,
+    program_basename: &str,
+    cache: Option<&core::EnvCache>,
+) -> Result<ShimmedBinaryResult, Error> {
+    let mut envvars_override = BTreeMap::<OsString, OsString>::new();
+    let mut envvars_remove = BTreeSet::<OsString>::new();
+
+    if let Some(cache) = cache {
+        envvars_override.extend(cache.resolve_current());
+        envvars_remove.extend(cache.unset_vars());
+    }
+
     let old_path = envvars_override
         .get(OsStr::new("PATH"))
         .cloned()
@@ -770,9 +2425,7 @@ fn find_shimmed_binary(
     let mut new_path = OsString::new();
 
     for entry in std::env::split_paths(&old_path) {
-        if fastenv_home.join("bin") == entry
-            || std::fs::canonicalize(&entry).map_or(false, |x| x == fastenv_home.join("bin"))
-        {
+        if is_own_bin_dir(&fastenv_home.join("bin"), &entry) {
             log::debug!("removing own entry from PATH: {}", entry.display());
             continue;
         }
@@ -784,14 +2437,10 @@ fn find_shimmed_binary(
         new_path.push(entry);
     }
 
+    // PATH is always passed on, even if .envrc unset it, as we need it to find the binary.
+    envvars_remove.remove(OsStr::new("PATH"));
     envvars_override.insert(OsStr::new("PATH").to_owned(), new_path);
 
-    let program_basename = Path::new(&program_name)
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap();
-
     let path = which::which_in(
         program_basename,
         envvars_override.get(OsStr::new("PATH")),
@@ -802,6 +2451,124 @@ fn find_shimmed_binary(
     Ok(ShimmedBinaryResult {
         path,
         envvars_override,
+        envvars_remove,
+    })
+}
+
+/// Read the env cache a shim should apply. With the `auto_reload` setting, a missing or stale cache
+/// is reloaded first.
+fn read_cache_for_shim(
+    fastenv_home: &Path,
+    ctx: &core::EnvrcContext,
+) -> Result<Option<core::EnvCache>, Error> {
+    let cache =
+        core::read_env_cache(ctx).context("failed to get environment variables from .envrc")?;
+
+    if settings::get().auto_reload && needs_reload(ctx, cache.as_ref())? {
+        if let Some(cache) = auto_reload(fastenv_home, ctx)? {
+            return Ok(Some(cache));
+        }
+    }
+
+    if let Some(ref cache) = cache {
+        check_stale_cache(ctx, cache)?;
+        mark_env_cache_used(ctx);
+    }
+
+    Ok(cache)
+}
+
+/// Failing to record that the cache was used is not worth failing the command over.
+fn mark_env_cache_used(ctx: &core::EnvrcContext) {
+    if let Err(e) = core::mark_env_cache_used(ctx) {
+        log::debug!("failed to mark env cache as used: {}", e);
+    }
+}
+
+fn needs_reload(ctx: &core::EnvrcContext, cache: Option<&core::EnvCache>) -> Result<bool, Error> {
+    match cache {
+        None => Ok(true),
+        Some(cache) => Ok(!cache.stale_reasons(ctx)?.is_empty()),
+    }
+}
+
+/// Reload the env cache from within a shim. Returns `None` if that was not possible, in which case
+/// the shim carries on with whatever cache there is.
+fn auto_reload(
+    fastenv_home: &Path,
+    ctx: &core::EnvrcContext,
+) -> Result<Option<core::EnvCache>, Error> {
+    // Shims never prompt, so files that have not been allowed are left alone rather than failing
+    // the command.
+    for layer in ctx.layers() {
+        let contents = std::fs::read(&layer.envrc_path)
+            .with_context(|| format!("failed to read {}", layer.envrc_path.display()))?;
+        let status = trust::check(fastenv_home, &layer.envrc_path, &contents)?;
+        if status != trust::TrustStatus::Allowed {
+            log::warn!(
+                "not reloading {} automatically, as it is {}. Review its contents and run {} to \
+                approve it.",
+                style(layer.envrc_path.display()).cyan(),
+                status.as_str(),
+                style("'fastenv allow'").magenta()
+            );
+            return Ok(None);
+        }
+    }
+
+    let _lock = core::lock_env_cache(ctx)?;
+
+    // Another shim may have reloaded while we were waiting for the lock.
+    let cache = core::read_env_cache(ctx)?;
+    if !needs_reload(ctx, cache.as_ref())? {
+        return Ok(cache);
+    }
+
+    log::debug!("reloading {} from shim", ctx.envrc_path.display());
+
+    if let Err(e) = compute_envvars(fastenv_home, ctx, true) {
+        log::warn!(
+            "failed to reload {} automatically: {:#}",
+            style(ctx.envrc_path.display()).cyan(),
+            e
+        );
+        return Ok(None);
+    }
+
+    Ok(core::read_env_cache(ctx)?)
+}
+
+fn check_stale_cache(ctx: &core::EnvrcContext, cache: &core::EnvCache) -> Result<(), Error> {
+    let policy = settings::get().stale_cache;
+    if policy == core::StalePolicy::Ignore {
+        return Ok(());
+    }
+
+    let reasons = cache.stale_reasons(ctx)?;
+
+    if !reasons.is_empty() {
+        let reason = reasons.join(", ");
+        if policy == core::StalePolicy::Error {
+            return Err(anyhow::anyhow!(
+                "cached environment is stale: {reason}. Run 'fastenv reload' to update it."
+            ));
+        }
+
+        log::warn!(
+            "cached environment is stale: {}. Run {} to update it.\n\
+            Set FASTENV_STALE_CACHE=ignore to silence this message.",
+            reason,
+            style("'fastenv reload'").magenta(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether fastenv was invoked through a shim rather than as 'fastenv'.
+fn invoked_as_shim() -> bool {
+    std::env::args_os().next().is_some_and(|program_name| {
+        Path::new(&program_name).file_name() != Some(OsStr::new("fastenv"))
     })
 }
 
@@ -824,12 +2591,44 @@ fn check_for_shim() -> Result<(), Error> {
         return Ok(());
     }
 
-    exec_shimmed_binary(&program_name, args_iter.collect())
+    exec_shimmed_binary(&program_name, args_iter.collect(), true)
         .with_context(|| format!("failed to run {}", program_basename))
 }
 
 fn command_exec(program_name: OsString, args: Vec<OsString>) -> Result<(), Error> {
-    exec_shimmed_binary(&program_name, args)
+    exec_shimmed_binary(&program_name, args, false)
+}
+
+fn command_stdlib(posix: bool) -> Result<(), Error> {
+    let stdlib = if posix {
+        include_str!("stdlib_posix.sh")
+    } else {
+        include_str!("stdlib.sh")
+    };
+    io::stdout().write_all(stdlib.as_bytes())?;
+    Ok(())
+}
+
+fn command_dotenv(path: &Path) -> Result<(), Error> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let vars = dotenv::parse(&contents, |name| std::env::var(name).ok())
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let env: core::Env = vars
+        .into_iter()
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    export::write_vars(
+        &mut stdout,
+        export::VarsFormat::Bash,
+        &env,
+        &BTreeSet::new(),
+    )?;
+    stdout.flush()?;
+    Ok(())
 }
 
 fn command_which(program_name: OsString, pretend_shimmed: bool) -> Result<(), Error> {
@@ -841,7 +2640,7 @@ fn command_which(program_name: OsString, pretend_shimmed: bool) -> Result<(), Er
         std::process::exit(1);
     }
 
-    let shimmed_binary_result = find_shimmed_binary(&fastenv_home, &program_name)?;
+    let shimmed_binary_result = find_shimmed_binary(&fastenv_home, &program_name, true)?;
     println!("{}", shimmed_binary_result.path.display());
     Ok(())
 }
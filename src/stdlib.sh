@@ -0,0 +1,303 @@
+# fastenv's built-in standard library for .envrc files.
+#
+# This implements the commonly used subset of direnv's stdlib, so that .envrc files written for
+# direnv work without direnv being installed. It is used instead of 'direnv stdlib' when direnv is
+# not on PATH, or when FASTENV_STDLIB=builtin is set.
+#
+# watch_file is defined by fastenv itself when evaluating .envrc, and __fastenv_bin points to the
+# fastenv binary.
+
+# Usage: log_status [<message> ...]
+log_status() {
+  echo "fastenv: $*" >&2
+}
+
+# Usage: log_error [<message> ...]
+log_error() {
+  echo "fastenv: error: $*" >&2
+}
+
+# Usage: has <command>
+#
+# Returns 0 if the command is available.
+has() {
+  type "$1" &>/dev/null
+}
+
+# Usage: expand_path <rel_path> [<relative_to>]
+#
+# Outputs the absolute path of <rel_path> relative to <relative_to> or the current directory,
+# without resolving symlinks.
+expand_path() {
+  local path=$1 base=${2:-$PWD} part
+  local -a parts out=()
+  [[ $base == /* ]] || base=$PWD/$base
+  [[ $path == /* ]] || path=$base/$path
+  IFS=/ read -ra parts <<<"$path"
+  for part in ${parts[@]+"${parts[@]}"}; do
+    case $part in
+      '' | .) ;;
+      ..) ((${#out[@]} == 0)) || unset 'out[${#out[@]}-1]' ;;
+      *) out+=("$part") ;;
+    esac
+  done
+  (IFS=/ && echo "/${out[*]}")
+}
+
+# Usage: find_up <filename>
+#
+# Outputs the path of <filename> in the current or the nearest parent directory containing it.
+find_up() {
+  (
+    while true; do
+      if [[ -f $1 ]]; then
+        echo "$PWD/$1"
+        return 0
+      fi
+      if [[ $PWD == / ]]; then
+        return 1
+      fi
+      cd ..
+    done
+  )
+}
+
+# Usage: path_add <varname> <path> [<path> ...]
+#
+# Expands the paths and prepends them to the colon-separated variable, in the order given.
+path_add() {
+  local var_name=$1 i
+  local -a path_array
+  IFS=: read -ra path_array <<<"${!var_name-}"
+  shift
+  for ((i = $#; i > 0; i--)); do
+    path_array=("$(expand_path "${!i}")" ${path_array[@]+"${path_array[@]}"})
+  done
+  export "$var_name=$(IFS=: && echo "${path_array[*]}")"
+}
+
+# Usage: PATH_add <path> [<path> ...]
+PATH_add() {
+  path_add PATH "$@"
+}
+
+# Usage: MANPATH_add <path>
+MANPATH_add() {
+  path_add MANPATH "$@"
+}
+
+# Usage: path_rm <varname> <pattern> [<pattern> ...]
+#
+# Removes all entries matching any of the glob patterns from the colon-separated variable.
+path_rm() {
+  local var_name=$1 entry pattern
+  local -a path_array results=()
+  IFS=: read -ra path_array <<<"${!var_name-}"
+  shift
+  for entry in ${path_array[@]+"${path_array[@]}"}; do
+    for pattern in "$@"; do
+      # shellcheck disable=SC2053
+      [[ $entry == $pattern ]] && continue 2
+    done
+    results+=("$entry")
+  done
+  export "$var_name=$(IFS=: && echo "${results[*]}")"
+}
+
+# Usage: PATH_rm <pattern> [<pattern> ...]
+PATH_rm() {
+  path_rm PATH "$@"
+}
+
+# Usage: dotenv [<dotenv>]
+#
+# Loads a .env file into the environment. Defaults to .env in the current directory.
+dotenv() {
+  local path=${1:-.env}
+  [[ -d $path ]] && path=$path/.env
+  watch_file "$path"
+  if ! [[ -f $path ]]; then
+    log_error ".env at $path not found"
+    return 1
+  fi
+  local exports
+  exports=$("$__fastenv_bin" dotenv "$path") || return 1
+  eval "$exports"
+}
+
+# Usage: dotenv_if_exists [<dotenv>]
+#
+# Like dotenv, but does nothing if the file does not exist.
+dotenv_if_exists() {
+  local path=${1:-.env}
+  [[ -d $path ]] && path=$path/.env
+  watch_file "$path"
+  if ! [[ -f $path ]]; then
+    return
+  fi
+  local exports
+  exports=$("$__fastenv_bin" dotenv "$path") || return 1
+  eval "$exports"
+}
+
+# Usage: source_env <file_or_dir_path>
+#
+# Loads another .envrc, either by specifying its path or the directory containing it. The file is
+# evaluated from within its own directory.
+source_env() {
+  local rcpath=${1/#\~/$HOME}
+  [[ -d $rcpath ]] && rcpath=$rcpath/.envrc
+  rcpath=$(expand_path "$rcpath")
+  watch_file "$rcpath"
+  if ! [[ -f $rcpath ]]; then
+    log_status "referenced $rcpath does not exist"
+    return 1
+  fi
+  pushd "${rcpath%/*}/" >/dev/null || return 1
+  log_status "loading $rcpath"
+  # shellcheck disable=SC1090
+  . "$rcpath"
+  popd >/dev/null || return 1
+}
+
+# Usage: source_env_if_exists <filename>
+source_env_if_exists() {
+  watch_file "$1"
+  if [[ -f $1 ]]; then
+    source_env "$1"
+  fi
+}
+
+# Usage: source_up [<filename>]
+#
+# Loads the nearest <filename> (.envrc by default) found in any parent directory.
+source_up() {
+  local dir file=${1:-.envrc}
+  dir=$(cd .. && find_up "$file") || {
+    log_status "no $file found in any parent directory"
+    return 1
+  }
+  source_env "$dir"
+}
+
+# Usage: source_up_if_exists [<filename>]
+source_up_if_exists() {
+  local dir file=${1:-.envrc}
+  dir=$(cd .. && find_up "$file") || return 0
+  source_env "$dir"
+}
+
+# Usage: env_vars_required <varname> [<varname> ...]
+#
+# Fails if any of the given variables is unset or empty.
+env_vars_required() {
+  local var ret=0
+  for var in "$@"; do
+    if [[ -z ${!var-} ]]; then
+      log_error "env var $var is required but missing/empty"
+      ret=1
+    fi
+  done
+  return "$ret"
+}
+
+# Usage: strict_env [<command> ...]
+#
+# Turns on shell execution strictness (errexit, nounset, pipefail).
+strict_env() {
+  if (($#)); then
+    (
+      set -euo pipefail
+      "$@"
+    )
+  else
+    set -euo pipefail
+  fi
+}
+
+# Usage: unstrict_env [<command> ...]
+unstrict_env() {
+  if (($#)); then
+    (
+      set +euo pipefail
+      "$@"
+    )
+  else
+    set +euo pipefail
+  fi
+}
+
+# Usage: direnv_layout_dir
+#
+# Outputs the directory layouts store their state in, .direnv by default.
+direnv_layout_dir() {
+  echo "${direnv_layout_dir:-$PWD/.direnv}"
+}
+
+# Usage: layout <type> [<args> ...]
+#
+# Runs layout_<type>, for example 'layout python'.
+layout() {
+  local name=$1
+  shift
+  "layout_$name" "$@"
+}
+
+# Usage: layout python [<python_exe>] [<venv args> ...]
+#
+# Creates and activates a virtualenv in .direnv/python-<version>.
+layout_python() {
+  local python=${1:-python} version
+  (($#)) && shift
+  unset PYTHONHOME
+  version=$("$python" -c "import platform; print(platform.python_version())") || {
+    log_error "could not determine version of $python"
+    return 1
+  }
+  VIRTUAL_ENV=$(direnv_layout_dir)/python-$version
+  if ! [[ -d $VIRTUAL_ENV ]]; then
+    "$python" -m venv "$@" "$VIRTUAL_ENV" || return 1
+  fi
+  export VIRTUAL_ENV
+  PATH_add "$VIRTUAL_ENV/bin"
+}
+
+# Usage: layout python3 [<venv args> ...]
+layout_python3() {
+  layout_python python3 "$@"
+}
+
+# Usage: layout node
+#
+# Adds node_modules/.bin to PATH.
+layout_node() {
+  PATH_add node_modules/.bin
+}
+
+# Usage: use <program> [<args> ...]
+#
+# Runs use_<program>, for example 'use nix'. Define your own use_<program> functions to add more.
+use() {
+  local name=$1
+  shift
+  log_status "using $name $*"
+  "use_$name" "$@"
+}
+
+# Usage: use nix [<nix-shell args> ...]
+#
+# Loads the environment of nix-shell (shell.nix or default.nix).
+use_nix() {
+  local env
+  watch_file shell.nix default.nix
+  env=$(nix-shell --run 'export -p' "$@") || return 1
+  eval "$(sed 's/^declare -x /export /' <<<"$env")"
+}
+
+# Usage: use flake [<installable>]
+#
+# Loads the development environment of a nix flake.
+use_flake() {
+  watch_file flake.nix flake.lock
+  eval "$(nix print-dev-env "$@")"
+}
@@ -0,0 +1,206 @@
+# fastenv's reduced standard library for .envrc files evaluated with a shell other than bash.
+#
+# This implements the part of the built-in stdlib that can be written in plain POSIX sh, and that
+# also works in zsh. Functions only use global variables prefixed with __fastenv_, as 'local' is
+# not part of POSIX.
+#
+# watch_file is defined by fastenv itself when evaluating .envrc, and __fastenv_bin points to the
+# fastenv binary.
+
+# Usage: log_status [<message> ...]
+log_status() {
+  echo "fastenv: $*" >&2
+}
+
+# Usage: log_error [<message> ...]
+log_error() {
+  echo "fastenv: error: $*" >&2
+}
+
+# Usage: has <command>
+#
+# Returns 0 if the command is available.
+has() {
+  command -v "$1" >/dev/null 2>&1
+}
+
+# Usage: expand_path <rel_path> [<relative_to>]
+#
+# Outputs the absolute path of <rel_path> relative to <relative_to> or the current directory.
+# Unlike the full stdlib, '.' and '..' are kept as they are.
+expand_path() {
+  case $1 in
+    /*) echo "$1" ;;
+    *) echo "${2:-$PWD}/$1" ;;
+  esac
+}
+
+# Usage: find_up <filename>
+#
+# Outputs the path of <filename> in the current or the nearest parent directory containing it.
+find_up() {
+  (
+    while true; do
+      if [ -f "$1" ]; then
+        echo "$PWD/$1"
+        return 0
+      fi
+      if [ "$PWD" = / ]; then
+        return 1
+      fi
+      cd .. || return 1
+    done
+  )
+}
+
+# Usage: path_add <varname> <path> [<path> ...]
+#
+# Expands the paths and prepends them to the colon-separated variable, in the order given.
+path_add() {
+  __fastenv_var=$1
+  __fastenv_prefix=
+  shift
+  for __fastenv_path in "$@"; do
+    __fastenv_prefix="$__fastenv_prefix${__fastenv_prefix:+:}$(expand_path "$__fastenv_path")"
+  done
+  eval "__fastenv_old=\${$__fastenv_var-}"
+  export "$__fastenv_var=$__fastenv_prefix${__fastenv_old:+:$__fastenv_old}"
+}
+
+# Usage: PATH_add <path> [<path> ...]
+PATH_add() {
+  path_add PATH "$@"
+}
+
+# Usage: MANPATH_add <path>
+MANPATH_add() {
+  path_add MANPATH "$@"
+}
+
+# Usage: dotenv [<dotenv>]
+#
+# Loads a .env file into the environment. Defaults to .env in the current directory.
+dotenv() {
+  __fastenv_dotenv=${1:-.env}
+  [ -d "$__fastenv_dotenv" ] && __fastenv_dotenv=$__fastenv_dotenv/.env
+  watch_file "$__fastenv_dotenv"
+  if ! [ -f "$__fastenv_dotenv" ]; then
+    log_error ".env at $__fastenv_dotenv not found"
+    return 1
+  fi
+  __fastenv_out=$("$__fastenv_bin" dotenv "$__fastenv_dotenv") || return 1
+  eval "$__fastenv_out"
+}
+
+# Usage: dotenv_if_exists [<dotenv>]
+#
+# Like dotenv, but does nothing if the file does not exist.
+dotenv_if_exists() {
+  __fastenv_dotenv=${1:-.env}
+  [ -d "$__fastenv_dotenv" ] && __fastenv_dotenv=$__fastenv_dotenv/.env
+  watch_file "$__fastenv_dotenv"
+  if ! [ -f "$__fastenv_dotenv" ]; then
+    return 0
+  fi
+  __fastenv_out=$("$__fastenv_bin" dotenv "$__fastenv_dotenv") || return 1
+  eval "$__fastenv_out"
+}
+
+# Usage: source_env <file_or_dir_path>
+#
+# Loads another .envrc, either by specifying its path or the directory containing it. The file is
+# evaluated from within its own directory.
+source_env() {
+  __fastenv_rcpath=$1
+  case $__fastenv_rcpath in
+    "~"/*) __fastenv_rcpath=$HOME/${__fastenv_rcpath#"~/"} ;;
+  esac
+  [ -d "$__fastenv_rcpath" ] && __fastenv_rcpath=$__fastenv_rcpath/.envrc
+  __fastenv_rcpath=$(expand_path "$__fastenv_rcpath")
+  watch_file "$__fastenv_rcpath"
+  if ! [ -f "$__fastenv_rcpath" ]; then
+    log_status "referenced $__fastenv_rcpath does not exist"
+    return 1
+  fi
+  log_status "loading $__fastenv_rcpath"
+  # the positional parameters survive a nested source_env, unlike global variables
+  set -- "$PWD" "$__fastenv_rcpath"
+  cd "${2%/*}/" || return 1
+  # shellcheck disable=SC1090
+  . "$2"
+  cd "$1" || return 1
+}
+
+# Usage: source_env_if_exists <filename>
+source_env_if_exists() {
+  watch_file "$1"
+  if [ -f "$1" ]; then
+    source_env "$1"
+  fi
+}
+
+# Usage: source_up [<filename>]
+#
+# Loads the nearest <filename> (.envrc by default) found in any parent directory.
+source_up() {
+  __fastenv_file=${1:-.envrc}
+  __fastenv_dir=$(cd .. && find_up "$__fastenv_file") || {
+    log_status "no $__fastenv_file found in any parent directory"
+    return 1
+  }
+  source_env "$__fastenv_dir"
+}
+
+# Usage: source_up_if_exists [<filename>]
+source_up_if_exists() {
+  __fastenv_dir=$(cd .. && find_up "${1:-.envrc}") || return 0
+  source_env "$__fastenv_dir"
+}
+
+# Usage: env_vars_required <varname> [<varname> ...]
+#
+# Fails if any of the given variables is unset or empty.
+env_vars_required() {
+  __fastenv_ret=0
+  for __fastenv_var in "$@"; do
+    eval "__fastenv_value=\${$__fastenv_var-}"
+    if [ -z "$__fastenv_value" ]; then
+      log_error "env var $__fastenv_var is required but missing/empty"
+      __fastenv_ret=1
+    fi
+  done
+  return "$__fastenv_ret"
+}
+
+# Usage: direnv_layout_dir
+#
+# Outputs the directory layouts store their state in, .direnv by default.
+direnv_layout_dir() {
+  echo "${direnv_layout_dir:-$PWD/.direnv}"
+}
+
+# Usage: layout <type> [<args> ...]
+#
+# Runs layout_<type>, for example 'layout node'.
+layout() {
+  __fastenv_name=$1
+  shift
+  "layout_$__fastenv_name" "$@"
+}
+
+# Usage: layout node
+#
+# Adds node_modules/.bin to PATH.
+layout_node() {
+  PATH_add node_modules/.bin
+}
+
+# Usage: use <program> [<args> ...]
+#
+# Runs use_<program>. Define your own use_<program> functions to add more.
+use() {
+  __fastenv_name=$1
+  shift
+  log_status "using $__fastenv_name $*"
+  "use_$__fastenv_name" "$@"
+}
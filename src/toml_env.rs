@@ -0,0 +1,167 @@
+//! `.fastenv.toml`, a declarative alternative to .envrc that fastenv evaluates without a shell:
+//!
+//! ```toml
+//! # .env files to load first, relative to the directory of .fastenv.toml
+//! dotenv = [".env"]
+//! # variables to remove
+//! unset = ["PYTHONHOME"]
+//!
+//! # variables to set. $VAR, ${VAR} and ${VAR:-default} refer to the environment before any of
+//! # these are set
+//! [env]
+//! DATABASE_URL = "postgres://localhost/${USER}"
+//!
+//! # entries to add to PATH, relative to the directory of .fastenv.toml
+//! [path]
+//! prepend = ["bin", "node_modules/.bin"]
+//! append = []
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::core::{self, Env};
+use crate::dotenv;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid .fastenv.toml")]
+    Parse(#[source] toml::de::Error),
+    #[error("failed to read {0}")]
+    ReadDotenv(PathBuf, #[source] io::Error),
+    #[error("failed to parse {0}")]
+    Dotenv(PathBuf, #[source] dotenv::Error),
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvFile {
+    #[serde(default)]
+    pub dotenv: Vec<String>,
+    #[serde(default)]
+    pub unset: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub path: PathConfig,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PathConfig {
+    #[serde(default)]
+    pub prepend: Vec<String>,
+    #[serde(default)]
+    pub append: Vec<String>,
+}
+
+pub fn parse(contents: &str) -> Result<EnvFile, Error> {
+    toml::from_str(contents).map_err(Error::Parse)
+}
+
+impl EnvFile {
+    /// Apply the file to `env`, resolving relative paths against `root`. Returns the .env files
+    /// that were read, so that they can be watched.
+    pub fn apply(&self, root: &Path, env: &mut Env) -> Result<Vec<PathBuf>, Error> {
+        let lookup_in = |env: &Env, name: &str| {
+            env.get(OsStr::new(name))
+                .map(|value| value.to_string_lossy().into_owned())
+        };
+
+        let mut watches = Vec::new();
+        for path in &self.dotenv {
+            let path = root.join(dotenv::expand(path, |name| lookup_in(env, name)));
+            let contents =
+                std::fs::read_to_string(&path).map_err(|e| Error::ReadDotenv(path.clone(), e))?;
+            let vars = dotenv::parse(&contents, |name| lookup_in(env, name))
+                .map_err(|e| Error::Dotenv(path.clone(), e))?;
+            env.extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+            watches.push(path);
+        }
+
+        for key in &self.unset {
+            env.remove(OsStr::new(key));
+        }
+
+        let values = self
+            .env
+            .iter()
+            .map(|(key, value)| {
+                let value = dotenv::expand(value, |name| lookup_in(env, name));
+                (OsString::from(key), OsString::from(value))
+            })
+            .collect::<Vec<_>>();
+
+        let resolve_entries = |entries: &[String]| {
+            entries
+                .iter()
+                .map(|entry| {
+                    root.join(dotenv::expand(entry, |name| lookup_in(env, name)))
+                        .into_os_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        let prepend = resolve_entries(&self.path.prepend);
+        let append = resolve_entries(&self.path.append);
+
+        env.extend(values);
+
+        if !prepend.is_empty() || !append.is_empty() {
+            let current = env.get(OsStr::new("PATH"));
+            let edit = core::PathEdit {
+                prepend,
+                append,
+                remove: Vec::new(),
+            };
+            let path = edit.apply(current.map(OsString::as_os_str));
+            env.insert("PATH".into(), path);
+        }
+
+        Ok(watches)
+    }
+}
+
+#[test]
+fn test_apply() {
+    let file = parse(
+        r#"
+unset = ["PYTHONHOME"]
+
+[env]
+GREETING = "hello ${USER}"
+LITERAL = "\\$USER"
+USER = "overridden"
+
+[path]
+prepend = ["bin"]
+append = ["$HOME/tools"]
+"#,
+    )
+    .unwrap();
+
+    let mut env: Env = maplit::btreemap![
+        "USER".into() => "world".into(),
+        "HOME".into() => "/home/world".into(),
+        "PYTHONHOME".into() => "/python".into(),
+        "PATH".into() => "/usr/bin".into(),
+    ];
+    let watches = file.apply(Path::new("/project"), &mut env).unwrap();
+
+    assert!(watches.is_empty());
+    assert_eq!(
+        env,
+        maplit::btreemap![
+            "GREETING".into() => "hello world".into(),
+            "HOME".into() => "/home/world".into(),
+            "LITERAL".into() => "$USER".into(),
+            "PATH".into() => "/project/bin:/usr/bin:/home/world/tools".into(),
+            "USER".into() => "overridden".into(),
+        ]
+    );
+
+    assert!(matches!(parse("bogus = 1\n"), Err(Error::Parse(_))));
+}
@@ -0,0 +1,102 @@
+//! Support for `.tool-versions` files as used by asdf and mise.
+//!
+//! Each line names a tool followed by one or more versions, of which fastenv uses the first. Tools
+//! are expected to be installed in `<tools dir>/<tool>/<version>/bin`, which is asdf's layout.
+
+use std::path::{Path, PathBuf};
+
+/// A single requested tool version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ToolVersion {
+    pub tool: String,
+    pub version: String,
+}
+
+/// Parse the contents of a .tool-versions file. Comments and empty lines are ignored.
+pub fn parse(contents: &str) -> Vec<ToolVersion> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            Some(ToolVersion {
+                tool: words.next()?.to_owned(),
+                version: words.next()?.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Return the directory tools are installed in: the `tools_dir` setting, or asdf's install
+/// directory.
+pub fn get_tools_dir() -> Option<PathBuf> {
+    if let Some(ref dir) = crate::settings::get().tools_dir {
+        return Some(dir.clone());
+    }
+
+    if let Some(dir) = std::env::var_os("ASDF_DATA_DIR").filter(|x| !x.is_empty()) {
+        return Some(Path::new(&dir).join("installs"));
+    }
+
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".asdf/installs"))
+}
+
+impl ToolVersion {
+    /// Return the directory to add to PATH for this tool, or `None` if the system version of the
+    /// tool should be used. A relative `path:` version is relative to `root`, the directory
+    /// containing the .tool-versions file.
+    pub fn bin_dir(&self, tools_dir: &Path, root: &Path) -> Option<PathBuf> {
+        if self.version == "system" {
+            None
+        } else if let Some(path) = self.version.strip_prefix("path:") {
+            Some(root.join(path).join("bin"))
+        } else {
+            Some(tools_dir.join(&self.tool).join(&self.version).join("bin"))
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let tools = parse("# comment\nnodejs 20.11.0 18.19.0\n\npython 3.12.1 # trailing\nruby\n");
+    assert_eq!(
+        tools,
+        vec![
+            ToolVersion {
+                tool: "nodejs".to_owned(),
+                version: "20.11.0".to_owned(),
+            },
+            ToolVersion {
+                tool: "python".to_owned(),
+                version: "3.12.1".to_owned(),
+            },
+        ]
+    );
+
+    let tools_dir = Path::new("/home/user/.asdf/installs");
+    let root = Path::new("/home/user/project");
+    assert_eq!(
+        tools[0].bin_dir(tools_dir, root),
+        Some(PathBuf::from(
+            "/home/user/.asdf/installs/nodejs/20.11.0/bin"
+        ))
+    );
+    let system = ToolVersion {
+        tool: "python".to_owned(),
+        version: "system".to_owned(),
+    };
+    assert_eq!(system.bin_dir(tools_dir, root), None);
+
+    let local = |version: &str| ToolVersion {
+        tool: "node".to_owned(),
+        version: version.to_owned(),
+    };
+    assert_eq!(
+        local("path:vendor/node").bin_dir(tools_dir, root),
+        Some(PathBuf::from("/home/user/project/vendor/node/bin"))
+    );
+    assert_eq!(
+        local("path:/opt/node").bin_dir(tools_dir, root),
+        Some(PathBuf::from("/opt/node/bin"))
+    );
+}
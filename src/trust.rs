@@ -0,0 +1,106 @@
+//! Records which .envrc files the user has approved for evaluation.
+//!
+//! Approvals live in `$FASTENV_HOME/allow/`, one file per .envrc, named after the hash of its path
+//! and containing the hash of the approved contents followed by the path. Denials live in
+//! `$FASTENV_HOME/deny/` and only contain the path.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::{hash_path, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustStatus {
+    /// The .envrc was allowed with exactly these contents.
+    Allowed,
+    /// The .envrc was allowed, but its contents have changed since.
+    Modified,
+    /// The .envrc was explicitly denied.
+    Denied,
+    /// The .envrc has never been allowed or denied.
+    Unknown,
+}
+
+impl TrustStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrustStatus::Allowed => "allowed",
+            TrustStatus::Modified => "modified since allowed",
+            TrustStatus::Denied => "denied",
+            TrustStatus::Unknown => "not allowed",
+        }
+    }
+}
+
+fn allow_path(fastenv_home: &Path, envrc_path: &Path) -> PathBuf {
+    fastenv_home.join("allow").join(hash_path(envrc_path))
+}
+
+fn deny_path(fastenv_home: &Path, envrc_path: &Path) -> PathBuf {
+    fastenv_home.join("deny").join(hash_path(envrc_path))
+}
+
+fn hash_contents(contents: &[u8]) -> String {
+    blake3::hash(contents).to_hex().to_string()
+}
+
+/// Determine whether the .envrc at `envrc_path` may be evaluated, given its current contents.
+pub fn check(
+    fastenv_home: &Path,
+    envrc_path: &Path,
+    contents: &[u8],
+) -> Result<TrustStatus, Error> {
+    if deny_path(fastenv_home, envrc_path).exists() {
+        return Ok(TrustStatus::Denied);
+    }
+
+    let record = match std::fs::read_to_string(allow_path(fastenv_home, envrc_path)) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(TrustStatus::Unknown),
+        Err(e) => return Err(e.into()),
+    };
+
+    match record.split_once(' ') {
+        Some((hash, _)) if hash == hash_contents(contents) => Ok(TrustStatus::Allowed),
+        _ => Ok(TrustStatus::Modified),
+    }
+}
+
+/// Approve the given contents of the .envrc at `envrc_path`, lifting any denial.
+pub fn allow(fastenv_home: &Path, envrc_path: &Path, contents: &[u8]) -> Result<(), Error> {
+    remove_if_exists(&deny_path(fastenv_home, envrc_path))?;
+
+    let path = allow_path(fastenv_home, envrc_path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(
+        path,
+        format!("{} {}\n", hash_contents(contents), envrc_path.display()),
+    )?;
+    Ok(())
+}
+
+/// Refuse to evaluate the .envrc at `envrc_path` regardless of its contents, until it is allowed
+/// again.
+pub fn deny(fastenv_home: &Path, envrc_path: &Path) -> Result<(), Error> {
+    remove_if_exists(&allow_path(fastenv_home, envrc_path))?;
+
+    let path = deny_path(fastenv_home, envrc_path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, format!("{}\n", envrc_path.display()))?;
+    Ok(())
+}
+
+/// Forget any approval or denial of the .envrc at `envrc_path`. Returns whether there was one.
+pub fn revoke(fastenv_home: &Path, envrc_path: &Path) -> Result<bool, Error> {
+    let allowed = remove_if_exists(&allow_path(fastenv_home, envrc_path))?;
+    let denied = remove_if_exists(&deny_path(fastenv_home, envrc_path))?;
+    Ok(allowed || denied)
+}
+
+fn remove_if_exists(path: &Path) -> Result<bool, Error> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -14,6 +14,14 @@ fn test_basic() -> Result<(), Error> {
     write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
     set_executable(harness.join("bogus/hello"))?;
 
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload",  @r###"
     success: true
     exit_code: 0
@@ -21,7 +29,7 @@ fn test_basic() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
     harness.which("hello").unwrap_err();
     assert_cmd!(harness, fastenv "shim" "hello",  @r###"
@@ -60,7 +68,7 @@ fn test_basic() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands. Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
     Ok(())
 }
@@ -152,7 +160,7 @@ fn test_verbosity() -> Result<(), Error> {
     ----- stderr -----
     [ERROR fastenv] failed to find .envrc in current or any parent directory
     "###);
-    harness.set_var("QUICKENV_LOG", "debug");
+    harness.set_var("FASTENV_LOG", "debug");
     assert_cmd!(harness, fastenv "vars",  @r###"
     success: false
     exit_code: 1
@@ -170,6 +178,14 @@ fn test_verbosity() -> Result<(), Error> {
 fn test_script_failure() -> Result<(), Error> {
     let harness = setup()?;
     write(harness.join(".envrc"), "exit 1")?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload",  @r###"
     success: false
     exit_code: 1
@@ -197,13 +213,21 @@ fn test_eating_own_tail() -> Result<(), Error> {
         harness.join(".envrc"),
         "bash -c 'echo hello world'; export PATH=bogus:$PATH",
     )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload",  @r###"
     success: true
     exit_code: 0
     ----- stdout -----
-    hello world
 
     ----- stderr -----
+    hello world
     "###);
     create_dir_all(harness.join("bogus"))?;
     write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
@@ -244,13 +268,21 @@ fn test_eating_own_tail2() -> Result<(), Error> {
         harness.join(".envrc"),
         "echo the value is $MYVALUE\nexport MYVALUE=canary",
     )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload",  @r###"
     success: true
     exit_code: 0
     ----- stdout -----
-    the value is
 
     ----- stderr -----
+    the value is
     "###);
     // assert that during reloading, we're not shimming bash and accidentally sourcing the old
     // envvar values. canary should not appear during reload.
@@ -258,9 +290,9 @@ fn test_eating_own_tail2() -> Result<(), Error> {
     success: true
     exit_code: 0
     ----- stdout -----
-    the value is
 
     ----- stderr -----
+    the value is
     "###);
     Ok(())
 }
@@ -274,6 +306,14 @@ fn test_exec() -> Result<(), Error> {
     write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
     set_executable(harness.join("bogus/hello"))?;
 
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -281,7 +321,7 @@ fn test_exec() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
 
     harness.which("hello").unwrap_err();
@@ -301,6 +341,14 @@ fn test_shim_creating_shims() -> Result<(), Error> {
     let harness = setup()?;
 
     write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -334,7 +382,7 @@ fn test_shim_creating_shims() -> Result<(), Error> {
 
     hello
 
-    Quickenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
+    fastenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
     Inside of [scrubbed $HOME]/project, those commands will run with .envrc enabled.
     Outside, they will run normally.
     Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
@@ -357,7 +405,7 @@ fn test_shim_creating_shims() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
 
     // fastenv shim should find the new command
@@ -371,7 +419,7 @@ fn test_shim_creating_shims() -> Result<(), Error> {
 
     hello2
 
-    Quickenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
+    fastenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
     Inside of [scrubbed $HOME]/project, those commands will run with .envrc enabled.
     Outside, they will run normally.
     Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
@@ -391,6 +439,14 @@ fn test_auto_shimming() -> Result<(), Error> {
     write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
     set_executable(harness.join("bogus/hello"))?;
 
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -398,7 +454,7 @@ fn test_auto_shimming() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
 
     assert_cmd!(harness, fastenv "shim" "-y", @r###"
@@ -411,7 +467,7 @@ fn test_auto_shimming() -> Result<(), Error> {
 
     hello
 
-    Quickenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
+    fastenv will create this new shim binary in [scrubbed $HOME]/.fastenv/bin/.
     Inside of [scrubbed $HOME]/project, those commands will run with .envrc enabled.
     Outside, they will run normally.
     Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
@@ -464,6 +520,14 @@ fn test_eating_own_tail3() -> Result<(), Error> {
     let mut harness = setup()?;
     harness.prepend_path(std::fs::canonicalize(harness.join("../.fastenv/bin")).unwrap());
     write(harness.join(".envrc"), "export PATH=hello:$PATH:")?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -480,7 +544,7 @@ fn test_eating_own_tail3() -> Result<(), Error> {
     Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
     Use 'fastenv unshim <command>' to remove them again.
     "###);
-    harness.set_var("QUICKENV_LOG", "debug");
+    harness.set_var("FASTENV_LOG", "debug");
     assert_cmd!(harness, hello, @r###"
     success: false
     exit_code: 1
@@ -512,6 +576,14 @@ fn test_which() -> Result<(), Error> {
     write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
     set_executable(harness.join("bogus/hello"))?;
 
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -519,7 +591,7 @@ fn test_which() -> Result<(), Error> {
 
     ----- stderr -----
     [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
-    Set QUICKENV_NO_SHIM_WARNINGS=1 to silence this message.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
     "###);
     assert_cmd!(harness, fastenv "which" "hello", @r###"
     success: false
@@ -565,6 +637,14 @@ fn test_which_pretend_shimmed() -> Result<(), Error> {
     [ERROR fastenv] "bash" is not shimmed by fastenv
     "###);
 
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
     assert_cmd!(harness, fastenv "reload", @r###"
     success: true
     exit_code: 0
@@ -604,3 +684,2600 @@ fn test_which_pretend_shimmed() -> Result<(), Error> {
     "###);
     Ok(())
 }
+
+#[test]
+fn test_stale_cache() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bogus/hello"))?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "shim" "hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+
+    // rewriting .envrc with the same contents does not invalidate the cache
+    write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    "###);
+
+    write(
+        harness.join(".envrc"),
+        "export PATH=bogus:$PATH\nexport FOO=bar\n",
+    )?;
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    harness.set_var("FASTENV_STALE_CACHE", "error");
+    assert_cmd!(harness, hello, @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to run shimmed command
+
+    Caused by:
+        0: failed to run hello
+        1: failed to find actual binary
+        2: cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    "###);
+
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] [scrubbed $HOME]/project/.envrc has changed since it was allowed. Review its contents and run 'fastenv allow' to approve it.
+    "###);
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_auto_reload() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "echo loading\nexport PATH=bogus:$PATH\nexport FOO=bar\n",
+    )?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello $FOO")?;
+    set_executable(harness.join("bogus/hello"))?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+    harness.set_var("FASTENV_AUTO_RELOAD", "1");
+
+    // files that have not been allowed are not evaluated
+    assert_cmd!(harness, fastenv "exec" "hello", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] not reloading [scrubbed $HOME]/project/.envrc automatically, as it is not allowed. Review its contents and run 'fastenv allow' to approve it.
+    [ERROR fastenv] failed to find actual binary
+
+    Caused by:
+        0: failed to find hello
+        1: cannot find binary path
+    "###);
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+
+    // the first use loads the environment, printing the output of .envrc to stderr
+    assert_cmd!(harness, fastenv "exec" "hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello bar
+
+    ----- stderr -----
+    loading
+    "###);
+    assert_cmd!(harness, fastenv "exec" "hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello bar
+
+    ----- stderr -----
+    "###);
+
+    write(
+        harness.join(".envrc"),
+        "echo loading\nexport PATH=bogus:$PATH\nexport FOO=baz\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+
+    // a stale cache is reloaded
+    assert_cmd!(harness, fastenv "exec" "hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello baz
+
+    ----- stderr -----
+    loading
+    "###);
+
+    // stdin is left to the shimmed command
+    write(
+        harness.join(".envrc"),
+        "cat > /dev/null\nexport PATH=bogus:$PATH\nexport FOO=qux\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, bash "-c" "echo data | fastenv exec sh -c 'hello && cat'", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello qux
+    data
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_reload() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "echo start >> log\nsleep 0.2\necho end >> log\nexport FOO=bar\n",
+    )?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+
+    // the second reload waits for the first one to finish instead of evaluating .envrc in parallel
+    assert_cmd!(harness, bash "-c" "fastenv reload & fastenv reload & wait; cat log", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    start
+    end
+    start
+    end
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    FOO=bar
+
+    ----- stderr -----
+    "###);
+
+    // in layered mode, reloads from sibling directories share the outer layer, which is evaluated
+    // only once as the second reload finds its cache fresh
+    std::fs::remove_file(harness.join("log"))?;
+    harness.set_var("FASTENV_LAYERED", "1");
+    write(
+        harness.join(".envrc"),
+        "echo start >> log\nsleep 0.2\necho end >> log\nexport FOO=layered\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    for sibling in ["a", "b"] {
+        create_dir_all(harness.join(sibling))?;
+        write(harness.join(sibling).join(".envrc"), "export BAR=baz\n")?;
+    }
+    assert_cmd!(harness, fastenv "allow" "a", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/a/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "allow" "b", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/b/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, bash "-c" "(cd a && fastenv reload) & (cd b && fastenv reload) & wait; cat log", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    start
+    end
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_stale_watched_file() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "watch_file data.txt missing.txt\nexport DATA=$(cat data.txt)\n",
+    )?;
+    write(harness.join("data.txt"), "hello")?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "shim" "true", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+    assert_cmd!(harness, true, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    write(harness.join("data.txt"), "world")?;
+    assert_cmd!(harness, true, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/data.txt has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    write(harness.join("missing.txt"), "")?;
+    assert_cmd!(harness, true, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/data.txt has changed, [scrubbed $HOME]/project/missing.txt was created. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, true, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_unset() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "unset PYTHONHOME\nexport FOO=bar\n")?;
+    harness.set_var("PYTHONHOME", "/somewhere");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo ${FOO-unset} ${PYTHONHOME-unset}", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    bar unset
+
+    ----- stderr -----
+    "###);
+
+    harness.set_var("FASTENV_SHIM_EXEC", "1");
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo ${FOO-unset} ${PYTHONHOME-unset}", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    bar unset
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_path_composes_with_caller() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export PATH=$PWD/bogus:$PATH\n")?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bogus/hello"))?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    // a PATH entry added after 'fastenv reload' is still visible to shimmed commands
+    create_dir_all(harness.join("extra"))?;
+    write(harness.join("extra/greet"), "#!/bin/sh\necho greetings")?;
+    set_executable(harness.join("extra/greet"))?;
+    harness.prepend_path(harness.join("extra"));
+
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "greet && hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    greetings
+    hello world
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_status() -> Result<(), Error> {
+    let harness = setup()?;
+
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: none found in current or any parent directory
+    shims: none
+    unshimmed commands: none
+
+    ----- stderr -----
+    "###);
+
+    write(
+        harness.join(".envrc"),
+        "export PATH=bogus:$PATH\nexport FOO=bar\n",
+    )?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bogus/hello"))?;
+
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: [scrubbed $HOME]/project/.envrc
+    trust: not allowed, run 'fastenv allow' to approve it
+    cache: [scrubbed $HOME]/.fastenv/envs/[hash]
+    cache status: missing, run 'fastenv reload' to create it
+    shims: none
+    unshimmed commands: none
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] 1 unshimmed commands (1 new). Use 'fastenv shim' to make them available.
+    Set FASTENV_NO_SHIM_WARNINGS=1 to silence this message.
+    "###);
+    assert_cmd!(harness, fastenv "shim" "true", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: [scrubbed $HOME]/project/.envrc
+    trust: allowed
+    cache: [scrubbed $HOME]/.fastenv/envs/[hash]
+    cache age: less than a minute
+    cache status: up to date
+    variables: 2 set, 0 unset
+    shims (1): true
+    unshimmed commands (1): hello
+
+    ----- stderr -----
+    "###);
+
+    write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    assert_cmd!(harness, fastenv "status" "--json", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    {
+      "cache": {
+        "age_secs": [age],
+        "created": [created],
+        "path": "[scrubbed $HOME]/.fastenv/envs/[hash]",
+        "stale": true,
+        "stale_reasons": [
+          "[scrubbed $HOME]/project/.envrc has changed"
+        ],
+        "variables_set": 2,
+        "variables_unset": 0
+      },
+      "envrc": "[scrubbed $HOME]/project/.envrc",
+      "parents": [],
+      "root": "[scrubbed $HOME]/project",
+      "shims": [
+        "true"
+      ],
+      "source": "envrc",
+      "trust": "modified since allowed",
+      "unshimmed_commands": [
+        "hello"
+      ]
+    }
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_vars_formats() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "export TRICKY=\"it's a\n\\$multiline value\"\nunset PYTHONHOME\n",
+    )?;
+    harness.set_var("PYTHONHOME", "/somewhere");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "vars" "--format" "bash", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    export TRICKY='it'\''s a
+    $multiline value'
+    unset PYTHONHOME
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "vars" "--format" "json", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    {
+      "PYTHONHOME": null,
+      "TRICKY": "it's a\n$multiline value"
+    }
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "vars" "--format" "yaml", @r###"
+    success: false
+    exit_code: 2
+    ----- stdout -----
+
+    ----- stderr -----
+    error: one of the values isn't valid for an argument
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "export PATH=/project/bin:${PATH#/removed/bin:}\nexport FOO=bar\nexport CHANGED=new\nunset PYTHONHOME\n",
+    )?;
+    harness.prepend_path("/removed/bin");
+    harness.set_var("PYTHONHOME", "/somewhere");
+    harness.set_var("CHANGED", "old");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "diff", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    ~ CHANGED
+        - old
+        + new
+    + FOO=bar
+    ~ PATH
+        + /project/bin
+        - /removed/bin
+    - PYTHONHOME=/somewhere
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_builtin_stdlib() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export FROM_PARENT=parent\n")?;
+    create_dir_all(harness.join("sub/bin"))?;
+    write(
+        harness.join("sub/.envrc"),
+        r#"
+source_up
+PATH_add bin
+dotenv
+env_vars_required FROM_PARENT FROM_DOTENV
+has definitely-not-a-command || export HAS_WORKS=1
+"#,
+    )?;
+    write(
+        harness.join("sub/.env"),
+        "# comment\nFROM_DOTENV=\"hello $FROM_PARENT\"\nexport SINGLE='$literal'\n",
+    )?;
+    write(harness.join("sub/bin/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("sub/bin/hello"))?;
+    harness.set_var("FASTENV_STDLIB", "builtin");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+    harness.cwd = harness.join("sub");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/sub/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    fastenv: loading [scrubbed $HOME]/project/.envrc
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "hello && echo $FROM_PARENT $FROM_DOTENV $SINGLE $HAS_WORKS", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+    parent hello parent $literal 1
+
+    ----- stderr -----
+    "###);
+
+    // the .env file is watched
+    write(harness.join(".env"), "FROM_DOTENV=changed\n")?;
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $FROM_DOTENV", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello parent
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/sub/.env has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    harness.set_var("FASTENV_STDLIB", "bogus");
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for FASTENV_STDLIB: "bogus" (expected auto, direnv or builtin)
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_stdlib_auto_detection() -> Result<(), Error> {
+    let mut harness = setup()?;
+    write(harness.join(".envrc"), "export FOO=bar\n")?;
+    create_dir_all(harness.join("fake-direnv"))?;
+    write(harness.join("fake-direnv/direnv"), "#!/bin/sh\n")?;
+    set_executable(harness.join("fake-direnv/direnv"))?;
+    harness.set_var("FASTENV_STALE_CACHE", "error");
+
+    // reload with direnv available, so its stdlib is picked
+    let path = harness.var("PATH").unwrap().to_owned();
+    let path_without_direnv = std::env::join_paths(
+        std::env::split_paths(&path).filter(|dir| !dir.join("direnv").exists()),
+    )?;
+    harness.set_var("PATH", &path_without_direnv);
+    harness.prepend_path(harness.join("fake-direnv"));
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    // shims started without direnv on PATH, such as from an IDE, still consider the cache fresh
+    harness.set_var("PATH", &path_without_direnv);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $FOO", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    bar
+
+    ----- stderr -----
+    "###);
+
+    harness.set_var("FASTENV_STDLIB", "builtin");
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $FOO", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to find actual binary
+
+    Caused by:
+        cached environment is stale: FASTENV_PRELUDE or FASTENV_STDLIB has changed. Run 'fastenv reload' to update it.
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_dotenv_parse_error() -> Result<(), Error> {
+    let mut harness = setup()?;
+    write(harness.join(".env"), "FOO=\"unterminated\n")?;
+    write(
+        harness.join(".envrc"),
+        "dotenv || echo dotenv failed with $?\nexport AFTER=1\n",
+    )?;
+    harness.set_var("FASTENV_STDLIB", "builtin");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to parse .env
+
+    Caused by:
+        line 1: unterminated quoted value
+    dotenv failed with 1
+    "###);
+
+    // the same with the reduced POSIX stdlib
+    write(
+        harness.join(".envrc"),
+        "#!/bin/sh\ndotenv_if_exists || echo dotenv failed with $?\nexport AFTER=1\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to parse .env
+
+    Caused by:
+        line 1: unterminated quoted value
+    dotenv failed with 1
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_trust() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export FOO=bar\n")?;
+    create_dir_all(harness.join("sub"))?;
+    write(harness.join("sub/.envrc"), "export FOO=sub\n")?;
+
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] [scrubbed $HOME]/project/.envrc is not allowed. Review its contents and run 'fastenv allow' to approve it.
+    "###);
+    assert_cmd!(harness, fastenv "allow" "sub", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/sub/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] [scrubbed $HOME]/project/.envrc is not allowed. Review its contents and run 'fastenv allow' to approve it.
+    "###);
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "deny", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Denied [scrubbed $HOME]/project/.envrc.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] [scrubbed $HOME]/project/.envrc is denied. Review its contents and run 'fastenv allow' to approve it.
+    "###);
+
+    assert_cmd!(harness, fastenv "revoke", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Revoked [scrubbed $HOME]/project/.envrc.
+    "###);
+    assert_cmd!(harness, fastenv "revoke", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [scrubbed $HOME]/project/.envrc was neither allowed nor denied.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] [scrubbed $HOME]/project/.envrc is not allowed. Review its contents and run 'fastenv allow' to approve it.
+    "###);
+
+    assert_cmd!(harness, fastenv "allow" "missing", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to find missing
+
+    Caused by:
+        No such file or directory (os error 2)
+    "###);
+
+    // directories are resolved to whichever file defines their environment, and only .envrc is
+    // looked for by default
+    create_dir_all(harness.join("dotenv-only"))?;
+    write(harness.join("dotenv-only/.env"), "FOO=bar\n")?;
+    assert_cmd!(harness, fastenv "allow" "dotenv-only", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to find .envrc in [scrubbed $HOME]/project/dotenv-only
+    "###);
+    harness.set_var("FASTENV_SOURCES", "envrc,dotenv");
+    assert_cmd!(harness, fastenv "allow" "dotenv-only", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/dotenv-only/.env. Run 'fastenv reload' to load it.
+    "###);
+    create_dir_all(harness.join("empty"))?;
+    assert_cmd!(harness, fastenv "allow" "empty", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to find .envrc or .env in [scrubbed $HOME]/project/empty
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_layered() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".envrc"),
+        "export ROOT=1\nexport SHARED=root\n",
+    )?;
+    create_dir_all(harness.join("service"))?;
+    write(
+        harness.join("service/.envrc"),
+        "export SERVICE=service-$ROOT\nexport SHARED=service\n",
+    )?;
+    let home = harness.var("HOME").unwrap().to_owned();
+    harness.set_var("FASTENV_LAYERED", "1");
+    harness.set_var("FASTENV_LAYERED_BOUNDARY", home);
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    harness.cwd = harness.join("service");
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/service/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $ROOT $SHARED $SERVICE", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    1 service service-1
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: [scrubbed $HOME]/project/service/.envrc
+    layered on:
+      [scrubbed $HOME]/project/.envrc
+    trust: allowed
+    cache: [scrubbed $HOME]/.fastenv/envs/[hash]
+    cache age: less than a minute
+    cache status: up to date
+    variables: 3 set, 0 unset
+    shims: none
+    unshimmed commands: none
+
+    ----- stderr -----
+    "###);
+
+    // changing the outermost .envrc invalidates the nested one
+    write(
+        harness.join("../.envrc"),
+        "export ROOT=2\nexport SHARED=root\n",
+    )?;
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $ROOT $SHARED $SERVICE", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    1 service service-1
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+    assert_cmd!(harness, fastenv "allow" "..", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $ROOT $SHARED $SERVICE", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    2 service service-2
+
+    ----- stderr -----
+    "###);
+
+    // reloading the outermost .envrc on its own invalidates the nested one as well
+    harness.cwd = harness.join("..");
+    write(
+        harness.join(".envrc"),
+        "export ROOT=3\nexport SHARED=root\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    harness.cwd = harness.join("service");
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $ROOT $SHARED $SERVICE", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    3 service service-2
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc was reloaded. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    // without layering, only the nearest .envrc applies
+    harness.set_var("FASTENV_LAYERED", "0");
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo ${ROOT-unset} $SHARED $SERVICE", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    unset service service-
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_sources() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".env"),
+        "GREETING=\"hello $USER_NAME\"\nexport LITERAL='$HOME'\n",
+    )?;
+    harness.set_var("USER_NAME", "world");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    // .env is only looked for once opted into
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to find .envrc in current or any parent directory
+    "###);
+    harness.set_var("FASTENV_SOURCES", "envrc,dotenv");
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.env. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $GREETING $LITERAL", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world $HOME
+
+    ----- stderr -----
+    "###);
+
+    // .envrc takes precedence over .env in the same directory
+    write(harness.join(".envrc"), "export GREETING=envrc\n")?;
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: [scrubbed $HOME]/project/.envrc
+    trust: not allowed, run 'fastenv allow' to approve it
+    cache: [scrubbed $HOME]/.fastenv/envs/[hash]
+    cache status: missing, run 'fastenv reload' to create it
+    shims: none
+    unshimmed commands: none
+
+    ----- stderr -----
+    "###);
+
+    harness.set_var("FASTENV_SOURCES", "dotenv");
+    assert_cmd!(harness, fastenv "status", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    .envrc: [scrubbed $HOME]/project/.env
+    trust: allowed
+    cache: [scrubbed $HOME]/.fastenv/envs/[hash]
+    cache age: less than a minute
+    cache status: up to date
+    variables: 2 set, 0 unset
+    shims: none
+    unshimmed commands: none
+
+    ----- stderr -----
+    "###);
+
+    create_dir_all(harness.join("tools/node/20.11.0/bin"))?;
+    write(
+        harness.join("tools/node/20.11.0/bin/node"),
+        "#!/bin/sh\necho node 20.11.0",
+    )?;
+    set_executable(harness.join("tools/node/20.11.0/bin/node"))?;
+    create_dir_all(harness.join("app"))?;
+    write(
+        harness.join("app/.tool-versions"),
+        "node 20.11.0 18.0.0\npython 3.12.1\nruby system\n",
+    )?;
+    let tools_dir = harness.join("tools");
+    harness.set_var("FASTENV_TOOLS_DIR", tools_dir);
+    harness.set_var("FASTENV_SOURCES", "tool-versions,envrc");
+    harness.cwd = harness.join("app");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/app/.tool-versions. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] python 3.12.1 is not installed, expected it at [scrubbed $HOME]/project/tools/python/3.12.1/bin
+    "###);
+    assert_cmd!(harness, fastenv "exec" "node", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    node 20.11.0
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "status" "--json", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    {
+      "cache": {
+        "age_secs": [age],
+        "created": [created],
+        "path": "[scrubbed $HOME]/.fastenv/envs/[hash]",
+        "stale": false,
+        "stale_reasons": [],
+        "variables_set": 1,
+        "variables_unset": 0
+      },
+      "envrc": "[scrubbed $HOME]/project/app/.tool-versions",
+      "parents": [],
+      "root": "[scrubbed $HOME]/project/app",
+      "shims": [],
+      "source": "tool-versions",
+      "trust": "allowed",
+      "unshimmed_commands": [
+        "node"
+      ]
+    }
+
+    ----- stderr -----
+    "###);
+
+    harness.set_var("FASTENV_SOURCES", "bogus");
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for FASTENV_SOURCES: "bogus" (expected a comma-separated list of toml, envrc, dotenv and tool-versions)
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_dotenv_directory() -> Result<(), Error> {
+    let mut harness = setup()?;
+    write(harness.join(".envrc"), "export FOO=bar\n")?;
+    // such as a virtualenv, which is not a dotenv file
+    create_dir_all(harness.join("sub/.env/bin"))?;
+    harness.set_var("FASTENV_SOURCES", "envrc,dotenv");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+    harness.cwd = harness.join("sub");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    FOO=bar
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_toml() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(
+        harness.join(".fastenv.toml"),
+        r#"
+dotenv = [".env"]
+unset = ["PYTHONHOME"]
+
+[env]
+GREETING = "hello ${USER_NAME}"
+FALLBACK = "${MISSING:-default}"
+
+[path]
+prepend = ["bin"]
+"#,
+    )?;
+    write(harness.join(".env"), "FROM_DOTENV=dotenv\n")?;
+    // .fastenv.toml takes precedence over .envrc
+    write(harness.join(".envrc"), "exit 1\n")?;
+    create_dir_all(harness.join("bin"))?;
+    write(harness.join("bin/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bin/hello"))?;
+    harness.set_var("USER_NAME", "world");
+    harness.set_var("PYTHONHOME", "/python");
+    harness.set_var("FASTENV_SOURCES", "toml,envrc");
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.fastenv.toml. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "hello && echo $GREETING $FALLBACK $FROM_DOTENV ${PYTHONHOME-unset}", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+    hello world default dotenv unset
+
+    ----- stderr -----
+    "###);
+
+    // included .env files are watched
+    write(harness.join(".env"), "FROM_DOTENV=changed\n")?;
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $FROM_DOTENV", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    dotenv
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.env has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+
+    write(harness.join(".fastenv.toml"), "[env]\nFOO = 1\n")?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.fastenv.toml. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to parse [scrubbed $HOME]/project/.fastenv.toml
+
+    Caused by:
+        0: invalid .fastenv.toml
+        1: TOML parse error at line 2, column 7
+             |
+           2 | FOO = 1
+             |       ^
+           invalid type: integer `1`, expected a string
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_shim_only_in() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    let project = harness.cwd.clone();
+    let other = home.join("other");
+
+    write(project.join(".envrc"), "export FOO=project\n")?;
+    create_dir_all(&other)?;
+    write(other.join(".envrc"), "export FOO=other\n")?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    harness.cwd = other.clone();
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/other/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    harness.cwd = project.clone();
+    assert_cmd!(harness, fastenv "shim" "--only-in" "." "printenv", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+    assert_cmd!(harness, printenv "FOO", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    project
+
+    ----- stderr -----
+    "###);
+
+    // outside of the project, the shim runs printenv as if it was not shimmed
+    harness.cwd = other.clone();
+    assert_cmd!(harness, printenv "FOO", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "printenv" "FOO", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    other
+
+    ----- stderr -----
+    "###);
+
+    // shimming without --only-in lifts the restriction
+    assert_cmd!(harness, fastenv "shim" "printenv", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    created no new shims.
+    "###);
+    assert_cmd!(harness, printenv "FOO", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    other
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_shim_registry() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    let project = harness.cwd.clone();
+
+    write(project.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    create_dir_all(project.join("bogus"))?;
+    for command in ["hello", "world"] {
+        write(project.join("bogus").join(command), "#!/bin/sh\necho hi")?;
+        set_executable(project.join("bogus").join(command))?;
+    }
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    No shims in [scrubbed $HOME]/.fastenv/bin/. Use 'fastenv shim' to create some.
+    "###);
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "shim" "--yes", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Found these unshimmed commands in your .envrc:
+
+    hello
+    world
+
+    fastenv will create these 2 new shim binaries in [scrubbed $HOME]/.fastenv/bin/.
+    Inside of [scrubbed $HOME]/project, those commands will run with .envrc enabled.
+    Outside, they will run normally.
+    Created 2 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    Use 'fastenv shim <command>' to run additional commands with .envrc enabled.
+    "###);
+
+    harness.cwd = home;
+    assert_cmd!(harness, fastenv "shim" "true", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+    assert_cmd!(harness, fastenv "list" "--long", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello (shim, auto)
+      resolves to: not found
+      project: [scrubbed $HOME]/project
+      created: less than a minute ago
+    true (shim, explicit)
+      resolves to: [scrubbed usr-bin]/true
+      project: none
+      created: less than a minute ago
+    world (shim, auto)
+      resolves to: not found
+      project: [scrubbed $HOME]/project
+      created: less than a minute ago
+
+    ----- stderr -----
+    "###);
+
+    harness.cwd = project;
+    assert_cmd!(harness, fastenv "unshim" "--project" ".", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed 2 shims from [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv shim <command>' to add them again
+    "###);
+    assert_cmd!(harness, fastenv "list" "--long", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    true (shim, explicit)
+      resolves to: [scrubbed usr-bin]/true
+      project: none
+      created: less than a minute ago
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_list() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let bin_dir = std::path::PathBuf::from(harness.var("HOME").unwrap()).join(".fastenv/bin");
+
+    write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bogus/hello"))?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "shim" "hello" "true", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 2 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+
+    write(bin_dir.join("foreign"), "#!/bin/sh\n")?;
+    set_executable(bin_dir.join("foreign"))?;
+    std::os::unix::fs::symlink("/nonexistent/fastenv", bin_dir.join("dangling"))?;
+
+    assert_cmd!(harness, fastenv "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    shims (2):
+    hello -> [scrubbed $HOME]/project/bogus/hello
+    true -> [scrubbed usr-bin]/true
+    foreign files (1):
+    foreign
+    dangling links (1):
+    dangling -> /nonexistent/fastenv
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "list" "--json", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    [
+      {
+        "created": null,
+        "kind": "dangling",
+        "link_target": "/nonexistent/fastenv",
+        "name": "dangling",
+        "only_in": [],
+        "origin": null,
+        "project": null,
+        "resolves_to": null
+      },
+      {
+        "created": null,
+        "kind": "foreign",
+        "link_target": null,
+        "name": "foreign",
+        "only_in": [],
+        "origin": null,
+        "project": null,
+        "resolves_to": null
+      },
+      {
+        "created": [created],
+        "kind": "shim",
+        "link_target": "[scrubbed $HOME]/.fastenv/fastenv_bin/fastenv",
+        "name": "hello",
+        "only_in": [],
+        "origin": "explicit",
+        "project": "[scrubbed $HOME]/project",
+        "resolves_to": "[scrubbed $HOME]/project/bogus/hello"
+      },
+      {
+        "created": [created],
+        "kind": "shim",
+        "link_target": "[scrubbed $HOME]/.fastenv/fastenv_bin/fastenv",
+        "name": "true",
+        "only_in": [],
+        "origin": "explicit",
+        "project": "[scrubbed $HOME]/project",
+        "resolves_to": "[scrubbed usr-bin]/true"
+      }
+    ]
+
+    ----- stderr -----
+    "###);
+
+    // outside of the project, hello is not found anymore
+    harness.cwd = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    assert_cmd!(harness, fastenv "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    shims (2):
+    hello -> not found
+    true -> [scrubbed usr-bin]/true
+    foreign files (1):
+    foreign
+    dangling links (1):
+    dangling -> /nonexistent/fastenv
+
+    ----- stderr -----
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_doctor() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    let bin_dir = home.join(".fastenv/bin");
+
+    write(harness.join(".envrc"), "export FOO=bar\n")?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+    harness.set_var("FASTENV_STDLIB", "builtin");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "shim" "true", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+    assert_cmd!(harness, fastenv "doctor", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    [pass] settings are valid
+    [warn] [scrubbed $HOME]/.fastenv/bin is on PATH, but not first
+           Executables in earlier PATH entries take precedence over shims. Move it to the front of PATH.
+    [pass] .envrc files without a shebang are evaluated with [scrubbed usr-bin]/bash
+    [pass] .envrc files are evaluated with the built-in stdlib
+    [pass] 1 shims in [scrubbed $HOME]/.fastenv/bin are valid
+    [pass] 1 env caches in [scrubbed $HOME]/.fastenv/envs are valid
+
+    ----- stderr -----
+    "###);
+
+    // a shim pointing at a fastenv binary that has since moved
+    std::os::unix::fs::symlink("/old/bin/fastenv", bin_dir.join("make"))?;
+    create_dir_all(harness.join("old"))?;
+    write(harness.join("old/fastenv"), "")?;
+    std::os::unix::fs::symlink(harness.join("old/fastenv"), bin_dir.join("node"))?;
+    // a shim shadowed by an executable earlier on PATH
+    create_dir_all(harness.join("shadow"))?;
+    write(harness.join("shadow/true"), "#!/bin/sh\n")?;
+    set_executable(harness.join("shadow/true"))?;
+    harness.prepend_path(harness.join("shadow"));
+    // a corrupt cache, and one whose .envrc is gone
+    write(home.join(".fastenv/envs/not-a-cache"), "garbage")?;
+    create_dir_all(harness.join("gone"))?;
+    write(harness.join("gone/.envrc"), "export FOO=gone\n")?;
+    harness.cwd = harness.join("gone");
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/gone/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    std::fs::remove_dir_all(&harness.cwd)?;
+    harness.cwd = home;
+
+    assert_cmd!(harness, fastenv "doctor", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+    [pass] settings are valid
+    [warn] [scrubbed $HOME]/.fastenv/bin is on PATH, but not first
+           Executables in earlier PATH entries take precedence over shims. Move it to the front of PATH.
+    [pass] .envrc files without a shebang are evaluated with [scrubbed usr-bin]/bash
+    [pass] .envrc files are evaluated with the built-in stdlib
+    [fail] [scrubbed $HOME]/.fastenv/bin/make links to /old/bin/fastenv, which does not exist
+           Run 'fastenv shim make' to recreate it, or 'fastenv unshim make' to remove it.
+    [warn] [scrubbed $HOME]/.fastenv/bin/node links to another fastenv binary at [scrubbed $HOME]/project/old/fastenv
+           Run 'fastenv shim node' to point it at the current one.
+    [fail] [scrubbed $HOME]/.fastenv/bin/true is shadowed by an executable of the same name at [scrubbed $HOME]/project/shadow/true
+           Move [scrubbed $HOME]/.fastenv/bin to the front of PATH, or remove the other executable.
+    [warn] [scrubbed $HOME]/.fastenv/envs/[hash] belongs to [scrubbed $HOME]/project/gone/.envrc, which no longer exists
+           Run 'fastenv gc' to remove it.
+    [fail] env cache at [scrubbed $HOME]/.fastenv/envs/not-a-cache is corrupt
+           Delete it and run 'fastenv reload' in its project.
+
+    ----- stderr -----
+    [ERROR fastenv] 3 checks failed.
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_gc() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    let project = harness.cwd.clone();
+    let gone = home.join("gone");
+
+    write(project.join(".envrc"), "export FOO=project\n")?;
+    create_dir_all(&gone)?;
+    write(gone.join(".envrc"), "export FOO=gone\n")?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    harness.cwd = gone.clone();
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/gone/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    harness.cwd = home.clone();
+    std::fs::remove_dir_all(&gone)?;
+
+    assert_cmd!(harness, fastenv "gc" "--dry-run", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Would remove [scrubbed $HOME]/.fastenv/envs/[hash] ([scrubbed $HOME]/gone/.envrc no longer exists)
+    Would remove 1 env caches.
+    "###);
+    assert_cmd!(harness, fastenv "gc", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed [scrubbed $HOME]/.fastenv/envs/[hash] ([scrubbed $HOME]/gone/.envrc no longer exists)
+    Removed 1 env caches.
+    "###);
+    assert_cmd!(harness, fastenv "gc", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed 0 env caches.
+    "###);
+
+    // caches that are still in use are only removed once they have not been used for a while
+    assert_cmd!(harness, fastenv "gc" "--older-than" "30d", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed 0 env caches.
+    "###);
+    for entry in std::fs::read_dir(home.join(".fastenv/envs"))? {
+        let long_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(40 * 86400);
+        std::fs::File::options()
+            .append(true)
+            .open(entry?.path())?
+            .set_modified(long_ago)?;
+    }
+    assert_cmd!(harness, fastenv "gc" "--older-than" "30d", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed [scrubbed $HOME]/.fastenv/envs/[hash] (not used in 40 days)
+    Removed 1 env caches.
+    "###);
+    assert_cmd!(harness, fastenv "gc" "--older-than" "1y", @r###"
+    success: false
+    exit_code: 2
+    ----- stdout -----
+
+    ----- stderr -----
+    error: invalid value for one of the arguments
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_config() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export PATH=bogus:$PATH\n")?;
+    create_dir_all(harness.join("bogus"))?;
+    write(harness.join("bogus/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("bogus/hello"))?;
+
+    assert_cmd!(harness, fastenv "config" "set" "no_shim_warnings" "1", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set no_shim_warnings in [scrubbed $HOME]/.fastenv/config.toml.
+    "###);
+    assert_cmd!(harness, fastenv "config" "set" "stale_cache" "error", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set stale_cache in [scrubbed $HOME]/.fastenv/config.toml.
+    "###);
+    assert_cmd!(harness, fastenv "config" "set" "stdlib" "bogus", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for stdlib: "bogus" (expected auto, direnv or builtin)
+    "###);
+    assert_cmd!(harness, fastenv "config" "set" "bogus" "1", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] unknown setting "bogus"
+    "###);
+    assert_cmd!(harness, fastenv "config" "set" "--project" "prelude" "echo hi", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] prelude can only be set in the global config file, not in [scrubbed $HOME]/project/.fastenv-config.toml
+    "###);
+    assert_cmd!(harness, fastenv "config" "get" "stale_cache", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    error
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "config" "get" "prelude", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    // no nag about shimming hello
+    assert_cmd!(harness, fastenv "shim" "hello", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Created 1 new shims in [scrubbed $HOME]/.fastenv/bin/.
+    Use 'fastenv unshim <command>' to remove them again.
+    "###);
+
+    write(
+        harness.join(".envrc"),
+        "export PATH=bogus:$PATH\nexport FOO=bar\n",
+    )?;
+    assert_cmd!(harness, hello, @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] failed to run shimmed command
+
+    Caused by:
+        0: failed to run hello
+        1: failed to find actual binary
+        2: cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    "###);
+
+    assert_cmd!(harness, fastenv "config" "set" "--project" "stale_cache" "ignore", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set stale_cache in [scrubbed $HOME]/project/.fastenv-config.toml.
+    "###);
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "config" "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    no_shim_warnings = true  # [scrubbed $HOME]/.fastenv/config.toml
+    stale_cache = "ignore"  # [scrubbed $HOME]/project/.fastenv-config.toml
+
+    ----- stderr -----
+    "###);
+
+    // environment variables take precedence over config files
+    harness.set_var("FASTENV_STALE_CACHE", "warn");
+    assert_cmd!(harness, fastenv "config" "set" "stale_cache" "ignore", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set stale_cache in [scrubbed $HOME]/.fastenv/config.toml.
+    [WARN fastenv] FASTENV_STALE_CACHE takes precedence over it.
+    "###);
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+    assert_cmd!(harness, fastenv "config" "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    no_shim_warnings = true  # [scrubbed $HOME]/.fastenv/config.toml
+    stale_cache = "warn"  # FASTENV_STALE_CACHE
+
+    ----- stderr -----
+    "###);
+
+    // an invalid project config file is ignored, see test_invalid_project_config
+    write(harness.join(".fastenv-config.toml"), "bogus = 1\n")?;
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: unknown setting "bogus" in [scrubbed $HOME]/project/.fastenv-config.toml
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+    assert_cmd!(harness, fastenv "config" "unset" "--project" "bogus", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: unknown setting "bogus" in [scrubbed $HOME]/project/.fastenv-config.toml
+    Removed bogus from [scrubbed $HOME]/project/.fastenv-config.toml.
+    "###);
+    assert_cmd!(harness, hello, @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: [scrubbed $HOME]/project/.envrc has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_project_config() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+    write(harness.join(".envrc"), "export FOO=bar\n")?;
+    write(
+        home.join(".fastenv/config.toml"),
+        "no_shim_warnings = true\n",
+    )?;
+    harness.set_var("FASTENV_STALE_CACHE", "error");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+
+    // project config files are not covered by 'fastenv allow', so a broken one in a cloned
+    // repository is ignored instead of breaking every shimmed command below it
+    write(harness.join(".fastenv-config.toml"), "bogus = 1\n")?;
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    FOO=bar
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: unknown setting "bogus" in [scrubbed $HOME]/project/.fastenv-config.toml
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $FOO", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    bar
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: unknown setting "bogus" in [scrubbed $HOME]/project/.fastenv-config.toml
+    "###);
+
+    write(
+        harness.join(".fastenv-config.toml"),
+        "sources = \"nonsense\"\n",
+    )?;
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    FOO=bar
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: invalid value for sources in [scrubbed $HOME]/project/.fastenv-config.toml: "nonsense" (expected a comma-separated list of toml, envrc, dotenv and tool-versions)
+    "###);
+
+    write(harness.join(".fastenv-config.toml"), "not toml\n")?;
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    FOO=bar
+
+    ----- stderr -----
+    [WARN fastenv] ignoring project config: invalid config file at [scrubbed $HOME]/project/.fastenv-config.toml
+    "###);
+
+    // the environment and global config file still apply, and their errors are not ignored
+    harness.set_var("FASTENV_STALE_CACHE", "maybe");
+    assert_cmd!(harness, fastenv "vars", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for FASTENV_STALE_CACHE: "maybe" (expected warn, error or ignore)
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_legacy_env_vars() -> Result<(), Error> {
+    let mut harness = setup()?;
+    let home = std::path::PathBuf::from(harness.var("HOME").unwrap());
+
+    harness.set_var("QUICKENV_HOME", home.join("legacy"));
+    harness.set_var("QUICKENV_STALE_CACHE", "error");
+    assert_cmd!(harness, fastenv "config" "set" "stdlib" "builtin", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set stdlib in [scrubbed $HOME]/legacy/config.toml.
+    "###);
+    assert_cmd!(harness, fastenv "config" "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    stdlib = "builtin"  # [scrubbed $HOME]/legacy/config.toml
+    stale_cache = "error"  # QUICKENV_STALE_CACHE
+
+    ----- stderr -----
+    "###);
+
+    // the new names take precedence
+    harness.set_var("FASTENV_HOME", home.join("new"));
+    harness.set_var("FASTENV_STALE_CACHE", "ignore");
+    assert_cmd!(harness, fastenv "config" "list", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    stale_cache = "ignore"  # FASTENV_STALE_CACHE
+
+    ----- stderr -----
+    "###);
+
+    harness.set_var("QUICKENV_STDLIB", "bogus");
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for QUICKENV_STDLIB: "bogus" (expected auto, direnv or builtin)
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_shell() -> Result<(), Error> {
+    let mut harness = setup()?;
+
+    write(harness.join(".envrc"), "export FROM_PARENT=parent\n")?;
+    create_dir_all(harness.join("sub/bin"))?;
+    write(
+        harness.join("sub/.envrc"),
+        r#"#!/usr/bin/env dash
+source_up
+PATH_add bin
+dotenv
+env_vars_required FROM_PARENT FROM_DOTENV
+has definitely-not-a-command || export HAS_WORKS=1
+export EVALUATED_BY="${BASH_VERSION:-posix}"
+"#,
+    )?;
+    write(
+        harness.join("sub/.env"),
+        "FROM_DOTENV=\"hello $FROM_PARENT\"\n",
+    )?;
+    write(harness.join("sub/bin/hello"), "#!/bin/sh\necho hello world")?;
+    set_executable(harness.join("sub/bin/hello"))?;
+    harness.set_var("FASTENV_NO_SHIM_WARNINGS", "1");
+    harness.cwd = harness.join("sub");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/sub/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    fastenv: loading [scrubbed $HOME]/project/.envrc
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "hello && echo $FROM_PARENT $FROM_DOTENV $HAS_WORKS $EVALUATED_BY", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    hello world
+    parent hello parent 1 posix
+
+    ----- stderr -----
+    "###);
+
+    // without a shebang, the shell setting applies
+    harness.cwd = harness.join("..");
+    write(
+        harness.join(".envrc"),
+        "export EVALUATED_BY=\"${BASH_VERSION:+bash}\"\n[ -n \"$EVALUATED_BY\" ] || export EVALUATED_BY=posix\n",
+    )?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "config" "set" "shell" "dash", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Set shell in [scrubbed $HOME]/.fastenv/config.toml.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $EVALUATED_BY", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    posix
+
+    ----- stderr -----
+    "###);
+
+    assert_cmd!(harness, fastenv "config" "unset" "shell", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed shell from [scrubbed $HOME]/.fastenv/config.toml.
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $EVALUATED_BY", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    posix
+
+    ----- stderr -----
+    [WARN fastenv] cached environment is stale: FASTENV_SHELL has changed. Run 'fastenv reload' to update it.
+    Set FASTENV_STALE_CACHE=ignore to silence this message.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    "###);
+    assert_cmd!(harness, fastenv "exec" "sh" "-c" "echo $EVALUATED_BY", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    bash
+
+    ----- stderr -----
+    "###);
+
+    assert_cmd!(harness, fastenv "config" "set" "shell" "fish", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] invalid value for shell: "fish" (expected bash, zsh, sh or the path to one of them)
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_envrc_output_and_tricky_values() -> Result<(), Error> {
+    let harness = setup()?;
+    // neither what .envrc prints nor the values it exports can be mistaken for the captured
+    // environment
+    write(
+        harness.join(".envrc"),
+        "echo 'FAKE=printed'\nprintf 'no trailing newline'\nexport TRICKY=\"line\nINJECTED=value\"\n",
+    )?;
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    FAKE=printed
+    no trailing newline
+    "###);
+    assert_cmd!(harness, fastenv "vars" "--format" "json", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+    {
+      "TRICKY": "line\nINJECTED=value"
+    }
+
+    ----- stderr -----
+    "###);
+
+    // the environment can not be captured if .envrc exits early
+    write(harness.join(".envrc"), "export FOO=bar\nexit 0\n")?;
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: false
+    exit_code: 1
+    ----- stdout -----
+
+    ----- stderr -----
+    [ERROR fastenv] .envrc exited before fastenv could read the resulting environment
+    "###);
+    Ok(())
+}
+
+#[test]
+fn test_envrc_prefix_and_log() -> Result<(), Error> {
+    let mut harness = setup()?;
+    write(
+        harness.join(".envrc"),
+        "echo hello\necho world\nprintf partial\nexport FOO=bar\n",
+    )?;
+    harness.set_var("FASTENV_ENVRC_PREFIX", "1");
+    harness.set_var("FASTENV_ENVRC_LOG", "1");
+
+    assert_cmd!(harness, fastenv "allow", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Allowed [scrubbed $HOME]/project/.envrc. Run 'fastenv reload' to load it.
+    "###);
+    assert_cmd!(harness, fastenv "reload", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    [envrc] hello
+    [envrc] world
+    [envrc] partial
+    "###);
+
+    let envs_dir = std::path::PathBuf::from(harness.var("HOME").unwrap()).join(".fastenv/envs");
+    let logs = std::fs::read_dir(envs_dir)?
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("log".as_ref()))
+        .collect::<Vec<_>>();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(std::fs::read_to_string(&logs[0])?, "hello\nworld\npartial");
+
+    // the log goes along with its cache
+    std::fs::remove_file(harness.join(".envrc"))?;
+    assert_cmd!(harness, fastenv "gc", @r###"
+    success: true
+    exit_code: 0
+    ----- stdout -----
+
+    ----- stderr -----
+    Removed [scrubbed $HOME]/.fastenv/envs/[hash] ([scrubbed $HOME]/project/.envrc no longer exists)
+    Removed 1 env caches.
+    "###);
+    assert!(!logs[0].exists());
+    Ok(())
+}
@@ -44,6 +44,9 @@ impl Harness {
             ),
             "[scrubbed usr-bin2]",
         );
+        insta_settings.add_filter(r"envs/[0-9a-f]{64}", "envs/[hash]");
+        insta_settings.add_filter(r#""age_secs": \d+"#, r#""age_secs": [age]"#);
+        insta_settings.add_filter(r#""created": \d+"#, r#""created": [created]"#);
         insta_settings
     }
 
@@ -75,7 +78,7 @@ impl Harness {
 
 impl Drop for Harness {
     fn drop(&mut self) {
-        if std::env::var("QUICKENV_TEST_LEAK_FILES").unwrap_or_default() == "1" {
+        if std::env::var("FASTENV_TEST_LEAK_FILES").unwrap_or_default() == "1" {
             std::mem::forget(self.home.take());
         }
     }
@@ -122,6 +125,7 @@ macro_rules! assert_cmd {
         insta_cmd::assert_cmd_snapshot!(
             Command::new($harness.which(stringify!($program_name))?)
             .current_dir(&$harness.cwd)
+            .env_remove("FASTENV_PRELUDE")
             .env_remove("QUICKENV_PRELUDE")
             .envs(&$harness.env)
             $(.arg($arg))*,